@@ -0,0 +1,71 @@
+//! The day 8 interpreter against compiling to bytecode and running that:
+//! `cargo bench --bench day8`. Compiling and running should come in under
+//! `exec` even for the straight-line program, which runs each line once.
+
+mod timing;
+
+use std::hint::black_box;
+
+use adventofcode_2017::day8::bytecode::Bytecode;
+use adventofcode_2017::day8::Cpu;
+use timing::bench;
+
+// Register names are letters only: aa, ab, ... for 0, 1, ...
+fn name(i: usize) -> String {
+    [i / 26 % 26, i % 26]
+        .iter()
+        .map(|&d| (b'a' + d as u8) as char)
+        .collect()
+}
+
+// A long straight-line program over a few hundred registers.
+fn program(lines: usize) -> String {
+    let cmps = [">", ">=", "<", "<=", "==", "!="];
+    (0..lines)
+        .map(|i| {
+            format!(
+                "{} {} {} if {} {} {}\n",
+                name(i * 7 % 300),
+                ["inc", "dec"][i % 2],
+                i % 100,
+                name(i * 13 % 300),
+                cmps[i % cmps.len()],
+                i % 50,
+            )
+        })
+        .collect()
+}
+
+// A short loop that runs a few hundred thousand steps.
+const LOOP: &str = "top:\n\
+                    n inc 1 if n >= 0\n\
+                    sum inc 3 if n > 10 and sum < 1000000\n\
+                    big inc 1 if n > 50000 or big < 0\n\
+                    jmp top if n < 100000";
+
+fn compare(name: &str, text: &str) {
+    let mut cpu = Cpu::new(text).unwrap();
+    cpu.set_step_limit(usize::MAX);
+
+    bench(&format!("{name}: exec"), || {
+        cpu.reset();
+        black_box(&mut cpu).exec().unwrap();
+        black_box(cpu.max());
+    });
+    bench(&format!("{name}: compile"), || {
+        black_box(Bytecode::compile(black_box(&cpu)));
+    });
+    let bytecode = Bytecode::compile(&cpu);
+    bench(&format!("{name}: run"), || {
+        black_box(black_box(&bytecode).run::<i32>(usize::MAX).unwrap().max());
+    });
+    bench(&format!("{name}: compile and run"), || {
+        let bytecode = Bytecode::compile(black_box(&cpu));
+        black_box(bytecode.run::<i32>(usize::MAX).unwrap().max());
+    });
+}
+
+fn main() {
+    compare("300k lines", &program(300_000));
+    compare("loop", LOOP);
+}
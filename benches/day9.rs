@@ -0,0 +1,38 @@
+//! The day 9 sequential stream scanner against summarizing chunks for the
+//! parallel one: `cargo bench --bench day9`.
+
+mod timing;
+
+use std::hint::black_box;
+
+use adventofcode_2017::day9::parallel::{score_parallel, Summary};
+use adventofcode_2017::day9::stream::scan;
+use timing::bench;
+
+// About `len` bytes of well-formed stream: groups nested a few deep, with
+// garbage, escapes and the odd multi-byte character.
+fn stream(len: usize) -> Vec<u8> {
+    let piece = "{{<a!>b,{c>},{<!!é{}>,{}},{{<x!y>}}},";
+    let mut out = String::with_capacity(len + piece.len());
+    out.push('{');
+    while out.len() < len {
+        out.push_str(piece);
+    }
+    out.push_str("{}}");
+    out.into_bytes()
+}
+
+fn main() {
+    let input = stream(16 << 20);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    bench("scan, 16 MiB", || {
+        black_box(scan(black_box(&input[..])).unwrap());
+    });
+    bench("summary, 16 MiB", || {
+        black_box(Summary::of(black_box(&input)).counts());
+    });
+    bench(&format!("parallel x{threads}, 16 MiB"), || {
+        black_box(score_parallel(black_box(&input), threads).counts());
+    });
+}
@@ -0,0 +1,40 @@
+//! Rough timings for the knot hash, without any benchmarking crates:
+//! `cargo bench --bench knot_hash`.
+
+use std::hint::black_box;
+
+mod timing;
+
+use adventofcode_2017::day10::batch::Batch;
+use adventofcode_2017::day10::{self, KnotHash};
+use timing::bench;
+
+fn main() {
+    let lengths = "197,97,204,108,1,29,5,71,0,50,2,255,248,78,254,63";
+
+    bench("part 1", || {
+        black_box(day10::solve_part1(black_box(lengths)));
+    });
+    bench("part 2", || {
+        black_box(day10::solve_part2(black_box(lengths)));
+    });
+    bench("digest of 1 KiB", || {
+        black_box(KnotHash::of(black_box(&[b'x'; 1024])));
+    });
+
+    let keys: Vec<_> = (0..128).map(|i| format!("flqrgnkx-{i}")).collect();
+    bench("128 keys, one by one", || {
+        for key in &keys {
+            black_box(day10::solve_part2(black_box(key)));
+        }
+    });
+
+    let batch: Batch = keys.iter().collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    bench("128 keys, batch", || {
+        black_box(black_box(&batch).digests(1));
+    });
+    bench("128 keys, batch threaded", || {
+        black_box(black_box(&batch).digests(threads));
+    });
+}
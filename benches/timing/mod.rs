@@ -0,0 +1,13 @@
+use std::time::{Duration, Instant};
+
+// Runs `f` for about half a second and reports the mean time per call.
+pub fn bench(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    let mut iters = 0u32;
+    while start.elapsed() < Duration::from_millis(500) {
+        f();
+        iters += 1;
+    }
+
+    println!("{:<24} {:>10.2?} per call", name, start.elapsed() / iters);
+}
@@ -0,0 +1,15 @@
+use std::env;
+use std::io;
+
+use adventofcode_2017::day8::debugger::Debugger;
+
+fn main() -> io::Result<()> {
+    let mut debugger = Debugger::new();
+    let mut stdout = io::stdout().lock();
+
+    if let Some(path) = env::args().nth(1) {
+        debugger.command(&format!("load {}", path), &mut stdout)?;
+    }
+
+    debugger.run(io::stdin().lock(), stdout)
+}
@@ -0,0 +1,30 @@
+use std::env;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use nom::combinator::all_consuming;
+
+use adventofcode_2017::day8::minimize::{minimize, Property};
+use adventofcode_2017::day8::Cpu;
+
+const USAGE: &str = "usage: day8-minimize FILE PROPERTY
+where PROPERTY is largest=N, max=N, overflow, divzero or steplimit";
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [path, property] = args.as_slice() else {
+        return Err(anyhow!(USAGE));
+    };
+    let property: Property = property.parse()?;
+
+    let text = fs::read_to_string(path)?;
+    let (_, program) = all_consuming(Cpu::parse_instructions)(text.trim_end())
+        .map_err(|e| anyhow!("Unable to parse {}: {}", path, e))?;
+
+    let Some(program) = minimize(&program, |candidate| property.holds(candidate)) else {
+        return Err(anyhow!("{} doesn't have the property {}", path, property));
+    };
+    print!("{}", Cpu::format_instructions(&program));
+
+    Ok(())
+}
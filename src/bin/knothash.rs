@@ -0,0 +1,153 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+use anyhow::{anyhow, Context, Result};
+
+use adventofcode_2017::day10::check::{format, parse_lengths, Line};
+use adventofcode_2017::day10::KnotHash;
+
+const USAGE: &str = "usage: knothash [-b] [-l] [-c] [FILE]...
+Prints the knot hash of each FILE, or of standard input if there are none
+or FILE is -.
+
+  -b, --binary   print hashes as 128 bits instead of hex
+  -l, --lengths  read input as comma-separated lengths from 0 to 255, as
+                 in the puzzle, and hash those numbers instead of the text
+  -c, --check    read `hash  filename` lines from each FILE and check them;
+                 a list read from standard input can't name - itself";
+
+#[derive(Default)]
+struct Options {
+    binary: bool,
+    lengths: bool,
+    check: bool,
+    files: Vec<String>,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options::default();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-b" | "--binary" => options.binary = true,
+            "-l" | "--lengths" => options.lengths = true,
+            "-c" | "--check" => options.check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            opt if opt.starts_with('-') && opt != "-" => {
+                return Err(anyhow!("unknown option {}\n{}", opt, USAGE));
+            }
+            _ => options.files.push(arg),
+        }
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+
+    Ok(options)
+}
+
+fn open(name: &str) -> Result<Box<dyn Read>> {
+    if name == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(name).with_context(|| format!("{}: unable to open", name))?;
+    Ok(Box::new(file))
+}
+
+fn digest(mut reader: impl Read, lengths: bool) -> Result<[u8; 16]> {
+    if !lengths {
+        let mut hash = KnotHash::new();
+        io::copy(&mut reader, &mut hash)?;
+        return Ok(hash.digest());
+    }
+
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(KnotHash::of(&parse_lengths(&text)?))
+}
+
+// How checking went, over every list.
+#[derive(Default)]
+struct Tally {
+    total: usize,
+    failed: usize,
+    malformed: usize,
+}
+
+// Checks every line of a list, printing how each went. A line that can't
+// be read is warned about and counted, and checking carries on. Standard
+// input can only be read once, so a list read from there can't name it
+// again.
+fn check(list: &str, lengths: bool, tally: &mut Tally) -> Result<()> {
+    let reader = BufReader::new(open(list)?);
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let entry = match Line::parse(&line) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("knothash: {}: {}: {:#}", list, number + 1, e);
+                tally.malformed += 1;
+                continue;
+            }
+        };
+
+        tally.total += 1;
+        let digest = if entry.name == "-" && list == "-" {
+            Err(anyhow!("-: the check list is being read from there"))
+        } else {
+            open(entry.name).and_then(|reader| digest(reader, lengths))
+        };
+        let ok = match digest {
+            Ok(digest) => entry.matches(&digest),
+            Err(e) => {
+                eprintln!("knothash: {:#}", e);
+                false
+            }
+        };
+        if !ok {
+            tally.failed += 1;
+        }
+        println!("{}: {}", entry.name, if ok { "OK" } else { "FAILED" });
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let options = parse_args()?;
+
+    if options.check {
+        let mut tally = Tally::default();
+        for name in &options.files {
+            check(name, options.lengths, &mut tally)?;
+        }
+        match tally.malformed {
+            0 => {}
+            1 => eprintln!("knothash: WARNING: 1 line is improperly formatted"),
+            n => eprintln!("knothash: WARNING: {} lines are improperly formatted", n),
+        }
+        if tally.failed > 0 {
+            return Err(anyhow!(
+                "{} of {} hashes did NOT match",
+                tally.failed,
+                tally.total
+            ));
+        }
+        if tally.malformed > 0 {
+            return Err(anyhow!("not every line could be checked"));
+        }
+        return Ok(());
+    }
+
+    for name in &options.files {
+        let digest = digest(open(name)?, options.lengths)
+            .with_context(|| format!("{}: unable to hash", name))?;
+        println!("{}  {}", format(&digest, options.binary), name);
+    }
+
+    Ok(())
+}
@@ -1,97 +1,264 @@
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
+
 use itertools::Itertools;
 
-struct Puzzle {
-    list: Vec<u8>,
+pub mod batch;
+pub mod check;
+pub mod custom;
+
+const SUFFIX: [u8; 5] = [17, 31, 73, 47, 23];
+
+// The list is the ring of `size` elements followed by as much room again,
+// for `reverse` to spill into.
+struct Puzzle<E = u8> {
+    list: Vec<E>,
+    size: usize,
     cursor: usize,
     skip: usize,
-    lengths: Vec<u8>,
+    lengths: Vec<usize>,
 }
 
-fn reverse(head: &mut [u8], tail: Option<&mut [u8]>) {
-    if let Some(tail) = tail {
-        // TODO: optimize
-        let mut nums = head.iter().chain(tail.iter()).copied().collect::<Vec<_>>();
-        nums.reverse();
-        for (h, n) in head.iter_mut().zip(nums.iter()) {
-            *h = *n;
-        }
-        for (t, n) in tail.iter_mut().zip(nums.iter().skip(head.len())) {
-            *t = *n;
-        }
-    } else {
-        head.reverse();
-    }
+// Reverses the `len` elements starting at `start`, wrapping around the end
+// of the ring: the first `size` elements of `list`, with at least as much
+// room after them. The part of a run that wraps is copied out past the
+// end, the run reversed in one piece, and that part copied back. Reversing
+// a contiguous slice is something the compiler turns into vector shuffles,
+// where swapping across the seam one pair at a time is not.
+fn reverse<E: Copy>(list: &mut [E], size: usize, start: usize, len: usize) {
+    let end = start + len;
+    let spill = end.saturating_sub(size);
+    let (ring, room) = list.split_at_mut(size);
+    room[..spill].copy_from_slice(&ring[..spill]);
+
+    list[start..end].reverse();
+
+    let (ring, room) = list.split_at_mut(size);
+    ring[..spill].copy_from_slice(&room[..spill]);
 }
 
-impl Puzzle {
-    fn new(last: u8, lengths: &str) -> Self {
-        let list = (0..=last).collect();
-        let lengths: Vec<u8> = lengths.split(',').filter_map(|n| n.parse().ok()).collect();
+impl<E: Copy> Puzzle<E> {
+    fn with_ring(mut list: Vec<E>, lengths: Vec<usize>) -> Self {
+        let size = list.len();
+        list.extend_from_within(..);
 
         Puzzle {
             list,
-            lengths,
+            size,
             cursor: 0,
             skip: 0,
+            lengths,
         }
     }
 
-    fn new2(last: u8, lengths: &str) -> Self {
-        let list = (0..=last).collect();
-        let mut lengths: Vec<u8> = lengths.as_bytes().to_vec();
-        lengths.extend([17, 31, 73, 47, 23].iter());
-
-        Puzzle {
-            list,
-            lengths,
-            cursor: 0,
-            skip: 0,
-        }
+    fn ring(&self) -> &[E] {
+        &self.list[..self.size]
     }
 
     fn hash(&mut self) {
         // run one round of hash
         for &length in self.lengths.iter() {
-            let (head, tail) = if self.cursor + (length as usize) < self.list.len() {
-                (
-                    &mut self.list[self.cursor..self.cursor + length as usize],
-                    None,
-                )
-            } else {
-                let (front, back) = self.list.split_at_mut(self.cursor);
-                let back_len = back.len();
-                (back, Some(&mut front[0..(length as usize - back_len)]))
-            };
-
-            reverse(head, tail);
-
-            self.cursor += length as usize + self.skip;
-            self.cursor %= self.list.len();
+            reverse(&mut self.list, self.size, self.cursor, length);
+
+            self.cursor += length + self.skip;
+            self.cursor %= self.size;
 
             self.skip += 1;
         }
     }
 
+    // Runs `rounds` rounds, then XORs each block of `block` elements down
+    // to one.
+    fn fold(mut self, rounds: usize, block: usize) -> Vec<E>
+    where
+        E: std::ops::BitXor<Output = E>,
+    {
+        for _ in 0..rounds {
+            self.hash();
+        }
+        self.blocks(block).collect()
+    }
+
+    fn blocks(&self, block: usize) -> impl Iterator<Item = E> + '_
+    where
+        E: std::ops::BitXor<Output = E>,
+    {
+        self.ring().chunks(block).map(|win| {
+            win.iter()
+                .copied()
+                .reduce(std::ops::BitXor::bitxor)
+                .unwrap()
+        })
+    }
+}
+
+impl Puzzle {
+    fn new(last: u8, lengths: &str) -> Self {
+        let lengths = lengths
+            .split(',')
+            .filter_map(|n| n.parse::<u8>().ok())
+            .map(usize::from)
+            .collect();
+
+        Puzzle::with_ring((0..=last).collect(), lengths)
+    }
+
+    fn new2(last: u8, lengths: &str) -> Self {
+        Self::from_bytes(last, lengths.as_bytes())
+    }
+
+    fn from_bytes(last: u8, bytes: &[u8]) -> Self {
+        let mut puzzle = Puzzle::with_ring((0..=last).collect(), Vec::new());
+        puzzle.restart(bytes);
+        puzzle
+    }
+
+    // Starts over with the lengths for `bytes`, keeping the buffers, so
+    // many inputs can be hashed one after another without allocating.
+    fn restart(&mut self, bytes: &[u8]) {
+        for (i, val) in self.list[..self.size].iter_mut().enumerate() {
+            *val = i as u8;
+        }
+        self.cursor = 0;
+        self.skip = 0;
+        self.lengths.clear();
+        self.lengths
+            .extend(bytes.iter().chain(&SUFFIX).copied().map(usize::from));
+    }
+
     fn solve_part1(mut self) -> u16 {
         self.hash();
         self.list[0] as u16 * self.list[1] as u16
     }
 
-    fn solve_part2(mut self) -> String {
-        for _ in 0..64 {
-            self.hash();
-        }
+    // The full 64 rounds, folded down to one byte per block of 16.
+    fn dense(self) -> Vec<u8> {
+        self.fold(64, 16)
+    }
 
-        self.list
-            .chunks(16)
-            .map(|win| {
-                win.iter()
-                    .copied()
-                    .reduce(std::ops::BitXor::bitxor)
-                    .unwrap()
-            })
-            .map(|val| format!("{val:02x}"))
-            .join("")
+    fn solve_part2(self) -> String {
+        self.dense().iter().map(|val| format!("{val:02x}")).join("")
+    }
+}
+
+/// The knot hash of a byte string, fed in as pieces of any size.
+///
+/// Every round runs over all the input, so nothing can be worked out until
+/// the end; the bytes are kept until `digest` is asked for. Besides its own
+/// methods it works as a `Hasher`, and as an `io::Write` for `io::copy`.
+/// `{:x}` and `{:X}` format the digest as hex, `{:b}` as 128 bits. With the
+/// `digest` feature it implements that crate's traits, `Digest` included,
+/// so it can stand in for other hashes in generic code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnotHash {
+    input: Vec<u8>,
+}
+
+impl KnotHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+    }
+
+    pub fn digest(&self) -> [u8; 16] {
+        Puzzle::from_bytes(255, &self.input)
+            .dense()
+            .try_into()
+            .unwrap()
+    }
+
+    /// The digest of `bytes` in one go.
+    pub fn of(bytes: &[u8]) -> [u8; 16] {
+        let mut hash = Self::new();
+        hash.update(bytes);
+        hash.digest()
+    }
+}
+
+impl Hasher for KnotHash {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    /// The first 8 bytes of the digest.
+    fn finish(&self) -> u64 {
+        u64::from_be_bytes(self.digest()[..8].try_into().unwrap())
+    }
+}
+
+impl io::Write for KnotHash {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.update(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "digest")]
+impl digest::HashMarker for KnotHash {}
+
+#[cfg(feature = "digest")]
+impl digest::OutputSizeUser for KnotHash {
+    type OutputSize = digest::consts::U16;
+}
+
+#[cfg(feature = "digest")]
+impl digest::Update for KnotHash {
+    fn update(&mut self, data: &[u8]) {
+        KnotHash::update(self, data);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl digest::FixedOutput for KnotHash {
+    fn finalize_into(self, out: &mut digest::Output<Self>) {
+        out.copy_from_slice(&self.digest());
+    }
+}
+
+#[cfg(feature = "digest")]
+impl digest::Reset for KnotHash {
+    fn reset(&mut self) {
+        self.input.clear();
+    }
+}
+
+#[cfg(feature = "digest")]
+impl digest::FixedOutputReset for KnotHash {
+    fn finalize_into_reset(&mut self, out: &mut digest::Output<Self>) {
+        out.copy_from_slice(&self.digest());
+        self.input.clear();
+    }
+}
+
+impl fmt::LowerHex for KnotHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.digest()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::UpperHex for KnotHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.digest()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02X}"))
+    }
+}
+
+impl fmt::Binary for KnotHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.digest()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:08b}"))
     }
 }
 
@@ -133,4 +300,64 @@ mod tests {
             "63960835bcdc130f0b66d7ff4f6a5a8e"
         );
     }
+
+    #[test]
+    fn reverse_wrapping() {
+        for n in 1..=9 {
+            for start in 0..n {
+                for len in 0..=n {
+                    let mut list: Vec<u8> = (0..2 * n as u8).collect();
+                    reverse(&mut list, n, start, len);
+                    list.truncate(n);
+
+                    let mut expected: Vec<u8> = (0..n as u8).collect();
+                    let run: Vec<_> = (start..start + len).map(|i| i % n).collect();
+                    for (&i, &j) in run.iter().zip(run.iter().rev()) {
+                        expected[i] = j as u8;
+                    }
+                    assert_eq!(list, expected, "n={n} start={start} len={len}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn knot_hash() {
+        let mut hash = KnotHash::new();
+        hash.update(b"AoC ");
+        hash.update(b"");
+        hash.update(b"2017");
+
+        assert_eq!(hash.digest(), KnotHash::of(b"AoC 2017"));
+        assert_eq!(format!("{:x}", hash), "33efeb34ea91902bb2f59c9920caa6cd");
+        assert_eq!(format!("{:X}", hash), "33EFEB34EA91902BB2F59C9920CAA6CD");
+
+        let bits = format!("{:b}", hash);
+        assert_eq!(bits.len(), 128);
+        assert!(bits.starts_with("0011001111101111"));
+
+        assert_eq!(hash.finish(), 0x33efeb34ea91902b);
+        assert_eq!(format!("{:x}", KnotHash::new()), solve_part2(""));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn knot_hash_digest() {
+        use digest::Digest;
+
+        fn generic<D: Digest>(data: &[u8]) -> Vec<u8> {
+            D::new_with_prefix(data).finalize().to_vec()
+        }
+
+        let expected = KnotHash::of(b"AoC 2017");
+        assert_eq!(<KnotHash as Digest>::digest(b"AoC 2017")[..], expected);
+
+        let mut hash = <KnotHash as Digest>::new();
+        Digest::update(&mut hash, b"AoC ");
+        Digest::update(&mut hash, b"2017");
+        assert_eq!(hash.finalize_reset()[..], expected);
+        assert_eq!(hash.finalize()[..], KnotHash::of(b""));
+        assert_eq!(generic::<KnotHash>(b"AoC 2017"), expected);
+        assert_eq!(<KnotHash as Digest>::output_size(), 16);
+    }
 }
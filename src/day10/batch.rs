@@ -0,0 +1,120 @@
+use std::thread;
+
+use super::Puzzle;
+
+/// Many inputs packed end to end in one buffer, to hash all at once.
+///
+/// Hashing one input is a long chain of reversals that each depend on the
+/// last, so the speed comes from around that: each thread works through
+/// its own share of the inputs with one hash it starts over for each, so
+/// there's no allocation per input. On one thread that's only a little
+/// faster than hashing in a loop; the real gain is from threads.
+///
+/// Inputs are hashed one at a time, not side by side. Every input reverses
+/// different stretches of its ring, so lanes of a vector have nothing in
+/// common to work on, and interleaving several rings measured no faster.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Batch {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, input: &[u8]) {
+        self.bytes.extend_from_slice(input);
+        self.ends.push(self.bytes.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(i)?;
+        let start = i.checked_sub(1).map_or(0, |i| self.ends[i]);
+        Some(&self.bytes[start..end])
+    }
+
+    /// `prefix` followed by each number in `range`, like the `key-0` to
+    /// `key-127` rows of a disk grid.
+    pub fn numbered(prefix: &str, range: std::ops::Range<usize>) -> Self {
+        range.map(|i| format!("{prefix}{i}")).collect()
+    }
+
+    /// The digest of every input, in order, shared out over `threads`.
+    pub fn digests(&self, threads: usize) -> Vec<[u8; 16]> {
+        let mut out = vec![[0; 16]; self.len()];
+        let size = self.len().div_ceil(threads.max(1)).max(1);
+
+        thread::scope(|scope| {
+            for (n, chunk) in out.chunks_mut(size).enumerate() {
+                scope.spawn(move || {
+                    let mut puzzle = Puzzle::from_bytes(255, &[]);
+                    for (i, digest) in chunk.iter_mut().enumerate() {
+                        puzzle.restart(self.get(n * size + i).unwrap());
+                        for _ in 0..64 {
+                            puzzle.hash();
+                        }
+                        for (out, val) in digest.iter_mut().zip(puzzle.blocks(16)) {
+                            *out = val;
+                        }
+                    }
+                });
+            }
+        });
+
+        out
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for Batch {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut batch = Self::new();
+        for input in iter {
+            batch.push(input.as_ref());
+        }
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day10::KnotHash;
+
+    #[test]
+    fn test_matches_single() {
+        let batch = Batch::numbered("flqrgnkx-", 0..128);
+        assert_eq!(batch.len(), 128);
+        assert_eq!(batch.get(10), Some(&b"flqrgnkx-10"[..]));
+        assert_eq!(batch.get(128), None);
+
+        let expected: Vec<_> = (0..128)
+            .map(|i| KnotHash::of(format!("flqrgnkx-{i}").as_bytes()))
+            .collect();
+        for threads in [0, 1, 3, 8, 200] {
+            assert_eq!(batch.digests(threads), expected);
+        }
+    }
+
+    #[test]
+    fn test_edge_inputs() {
+        let long = vec![255; 5000];
+        let batch: Batch = [&b""[..], b"AoC 2017", &long, b""].into_iter().collect();
+        let digests = batch.digests(2);
+
+        assert_eq!(digests[0], KnotHash::of(b""));
+        assert_eq!(digests[1], KnotHash::of(b"AoC 2017"));
+        assert_eq!(digests[2], KnotHash::of(&long));
+        assert_eq!(digests[3], digests[0]);
+        assert!(Batch::new().digests(4).is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+
+/// Reads comma-separated lengths from 0 to 255, the way the puzzle input
+/// is written. Unlike the puzzle's own parsing, an entry that isn't a
+/// length is an error rather than left out.
+pub fn parse_lengths(text: &str) -> Result<Vec<u8>> {
+    text.trim()
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse()
+                .map_err(|_| anyhow!("{:?} is not a length from 0 to 255", n))
+        })
+        .collect()
+}
+
+/// A digest as 32 hex digits, or as 128 bits.
+pub fn format(digest: &[u8; 16], binary: bool) -> String {
+    digest
+        .iter()
+        .map(|byte| {
+            if binary {
+                format!("{byte:08b}")
+            } else {
+                format!("{byte:02x}")
+            }
+        })
+        .collect()
+}
+
+/// One `hash  name` line of a check list, as printed by `knothash`. A `*`
+/// in place of the second space is accepted too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub expected: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> Line<'a> {
+    /// Reads a line of a check list, or `None` if it's blank.
+    pub fn parse(line: &'a str) -> Result<Option<Self>> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+
+        line.split_once(' ')
+            .and_then(|(expected, rest)| {
+                let name = rest.strip_prefix([' ', '*'])?;
+                Some(Some(Line { expected, name }))
+            })
+            .ok_or_else(|| anyhow!("improperly formatted line: {}", line))
+    }
+
+    /// Whether `digest` is the one expected, in whichever format the
+    /// expected hash is written.
+    pub fn matches(&self, digest: &[u8; 16]) -> bool {
+        let binary = self.expected.len() == 128;
+        self.expected.eq_ignore_ascii_case(&format(digest, binary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day10::KnotHash;
+
+    #[test]
+    fn test_parse_lengths() {
+        assert_eq!(parse_lengths("3,4,1,5\n").unwrap(), vec![3, 4, 1, 5]);
+        assert_eq!(parse_lengths(" 0, 255 ").unwrap(), vec![0, 255]);
+        assert_eq!(
+            parse_lengths("3,256").unwrap_err().to_string(),
+            "\"256\" is not a length from 0 to 255"
+        );
+        assert!(parse_lengths("").is_err());
+        assert!(parse_lengths("1,,2").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let digest = KnotHash::of(b"AoC 2017");
+        assert_eq!(format(&digest, false), "33efeb34ea91902bb2f59c9920caa6cd");
+
+        let bits = format(&digest, true);
+        assert_eq!(bits.len(), 128);
+        assert!(bits.starts_with("0011001111101111"));
+    }
+
+    #[test]
+    fn test_lines() {
+        let digest = KnotHash::of(b"AoC 2017");
+        let hex = format(&digest, false);
+        let bits = format(&digest, true);
+
+        let line = Line::parse("33EFEB34EA91902BB2F59C9920CAA6CD  some file")
+            .unwrap()
+            .unwrap();
+        assert_eq!(line.name, "some file");
+        assert!(line.matches(&digest));
+        assert!(!line.matches(&KnotHash::of(b"AoC 2018")));
+
+        let text = format!("{bits} *-");
+        let line = Line::parse(&text).unwrap().unwrap();
+        assert_eq!(line.name, "-");
+        assert!(line.matches(&digest));
+
+        assert_eq!(Line::parse("  ").unwrap(), None);
+        for bad in [hex.as_str(), "abc def", "abc"] {
+            assert_eq!(
+                Line::parse(bad).unwrap_err().to_string(),
+                format!("improperly formatted line: {}", bad)
+            );
+        }
+    }
+}
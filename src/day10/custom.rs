@@ -0,0 +1,253 @@
+use std::fmt;
+use std::ops::BitXor;
+
+use anyhow::{bail, ensure, Result};
+
+use super::{Puzzle, SUFFIX};
+
+/// What the ring can be made of. Every position from 0 to `size - 1` has
+/// to fit, so rings past 256 need something wider than `u8`.
+pub trait Element: Copy + BitXor<Output = Self> + fmt::Debug + fmt::LowerHex {
+    fn from_usize(n: usize) -> Option<Self>;
+}
+
+impl Element for u8 {
+    fn from_usize(n: usize) -> Option<Self> {
+        n.try_into().ok()
+    }
+}
+
+impl Element for u16 {
+    fn from_usize(n: usize) -> Option<Self> {
+        n.try_into().ok()
+    }
+}
+
+impl Element for u32 {
+    fn from_usize(n: usize) -> Option<Self> {
+        n.try_into().ok()
+    }
+}
+
+impl Element for u64 {
+    fn from_usize(n: usize) -> Option<Self> {
+        n.try_into().ok()
+    }
+}
+
+/// Sets up a knot hash with other parameters than the puzzle's. Starts out
+/// with the standard ones: 256 elements, the `17, 31, 73, 47, 23` suffix,
+/// 64 rounds and blocks of 16.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnotBuilder {
+    size: usize,
+    suffix: Vec<usize>,
+    rounds: usize,
+    block: usize,
+}
+
+impl Default for KnotBuilder {
+    fn default() -> Self {
+        Self {
+            size: 256,
+            suffix: SUFFIX.iter().copied().map(usize::from).collect(),
+            rounds: 64,
+            block: 16,
+        }
+    }
+}
+
+impl KnotBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many elements the ring has, numbered from 0.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The lengths added after every input.
+    pub fn suffix(mut self, suffix: &[usize]) -> Self {
+        self.suffix = suffix.to_vec();
+        self
+    }
+
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    /// How many elements are XORed into each one of the dense hash.
+    pub fn block(mut self, block: usize) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Checks the parameters fit together, and that the ring fits `E`.
+    pub fn build<E: Element>(&self) -> Result<Knot<E>> {
+        ensure!(self.size > 0, "the ring needs at least one element");
+        let Some(ring) = (0..self.size).map(E::from_usize).collect() else {
+            bail!(
+                "a ring of {} doesn't fit in {}",
+                self.size,
+                std::any::type_name::<E>()
+            );
+        };
+        ensure!(
+            self.block > 0 && self.size.is_multiple_of(self.block),
+            "a block size of {} doesn't divide a ring of {}",
+            self.block,
+            self.size
+        );
+
+        let knot = Knot {
+            ring,
+            suffix: self.suffix.clone(),
+            rounds: self.rounds,
+            block: self.block,
+        };
+        knot.check(&knot.suffix)?;
+
+        Ok(knot)
+    }
+}
+
+/// A knot hash with its parameters checked, ready to hash any number of
+/// inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Knot<E> {
+    ring: Vec<E>,
+    suffix: Vec<usize>,
+    rounds: usize,
+    block: usize,
+}
+
+impl<E: Element> Knot<E> {
+    pub fn size(&self) -> usize {
+        self.ring.len()
+    }
+
+    fn check(&self, lengths: &[usize]) -> Result<()> {
+        match lengths.iter().find(|&&length| length > self.size()) {
+            Some(length) => bail!("a length of {} is longer than the ring", length),
+            None => Ok(()),
+        }
+    }
+
+    fn puzzle(&self, mut lengths: Vec<usize>) -> Result<Puzzle<E>> {
+        self.check(&lengths)?;
+        lengths.extend(&self.suffix);
+
+        Ok(Puzzle::with_ring(self.ring.clone(), lengths))
+    }
+
+    /// The ring after all the rounds over `lengths` and the suffix. With
+    /// one round and no suffix, this is part 1.
+    pub fn sparse(&self, lengths: &[usize]) -> Result<Vec<E>> {
+        Ok(self.puzzle(lengths.to_vec())?.fold(self.rounds, 1))
+    }
+
+    /// The sparse hash of `bytes`, one length each, XORed down a block at a
+    /// time. Bytes only go up to 255, so this can fail only for rings
+    /// smaller than 256.
+    pub fn dense(&self, bytes: &[u8]) -> Result<Vec<E>> {
+        let lengths = bytes.iter().copied().map(usize::from).collect();
+        Ok(self.puzzle(lengths)?.fold(self.rounds, self.block))
+    }
+
+    /// The dense hash in hex, each element padded to the full width of `E`.
+    pub fn hex(&self, bytes: &[u8]) -> Result<String> {
+        let width = 2 * std::mem::size_of::<E>();
+        Ok(self
+            .dense(bytes)?
+            .iter()
+            .map(|val| format!("{val:0width$x}"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day10::solve_part2;
+
+    #[test]
+    fn test_standard() {
+        let knot = KnotBuilder::new().build::<u8>().unwrap();
+        for input in ["", "AoC 2017", "1,2,3", "1,2,4"] {
+            assert_eq!(knot.hex(input.as_bytes()).unwrap(), solve_part2(input));
+        }
+
+        // the same ring in a wider type hashes the same, just printed wider
+        let wide = KnotBuilder::new().build::<u16>().unwrap();
+        let narrow = knot.dense(b"AoC 2017").unwrap();
+        let widened: Vec<u16> = narrow.iter().copied().map(u16::from).collect();
+        assert_eq!(wide.dense(b"AoC 2017").unwrap(), widened);
+        assert_eq!(wide.hex(b"").unwrap().len(), 64);
+    }
+
+    #[test]
+    fn test_part1() {
+        let knot = KnotBuilder::new()
+            .size(5)
+            .suffix(&[])
+            .rounds(1)
+            .block(1)
+            .build::<u8>()
+            .unwrap();
+        assert_eq!(knot.sparse(&[3, 4, 1, 5]).unwrap(), vec![3, 4, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_large_ring() {
+        let knot = KnotBuilder::new()
+            .size(1000)
+            .suffix(&[999, 500, 1])
+            .rounds(10)
+            .block(40)
+            .build::<u16>()
+            .unwrap();
+
+        let mut sparse = knot.sparse(&[700, 3, 1000]).unwrap();
+        assert_eq!(sparse.len(), 1000);
+        sparse.sort_unstable();
+        assert!(sparse.iter().copied().eq(0..1000));
+
+        let dense = knot.dense(b"AoC 2017").unwrap();
+        assert_eq!(dense.len(), 25);
+        assert_ne!(dense, knot.dense(b"AoC 2018").unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |builder: KnotBuilder| builder.build::<u8>().unwrap_err().to_string();
+
+        assert_eq!(
+            error(KnotBuilder::new().size(300)),
+            "a ring of 300 doesn't fit in u8"
+        );
+        assert_eq!(
+            error(KnotBuilder::new().size(0)),
+            "the ring needs at least one element"
+        );
+        assert_eq!(
+            error(KnotBuilder::new().block(15)),
+            "a block size of 15 doesn't divide a ring of 256"
+        );
+        assert_eq!(
+            error(KnotBuilder::new().suffix(&[257])),
+            "a length of 257 is longer than the ring"
+        );
+
+        let small = KnotBuilder::new()
+            .size(10)
+            .suffix(&[])
+            .block(5)
+            .build::<u8>()
+            .unwrap();
+        assert!(small.dense(b"a").is_err());
+        assert!(small.sparse(&[10]).is_ok());
+    }
+}
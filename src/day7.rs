@@ -1,7 +1,6 @@
-/*
 use std::collections::{HashMap, HashSet};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, i32, newline, space1},
@@ -21,7 +20,7 @@ struct Node<'a> {
 }
 
 impl<'a> Node<'a> {
-    fn parse(input: &'a str) -> IResult<&str, Self> {
+    fn parse(input: &'a str) -> IResult<&'a str, Self> {
         map(
             tuple((
                 terminated(Self::parse_name, space1),
@@ -46,123 +45,352 @@ impl<'a> Node<'a> {
         delimited(tag("("), i32, tag(")"))(input)
     }
 
-    fn parse_children(input: &'a str) -> IResult<&str, Vec<&'a str>> {
+    fn parse_children(input: &'a str) -> IResult<&'a str, Vec<&'a str>> {
         let (input, _) = preceded(tag("->"), space1)(input)?;
         separated_list1(tuple((tag(","), space1)), Self::parse_name)(input)
     }
+
+    fn children(&self) -> &[&'a str] {
+        self.children.as_deref().unwrap_or_default()
+    }
 }
 
-struct Graph<'a>(HashMap<&'a str, Node<'a>>);
+/// The program whose weight must change to balance the tower, and the
+/// weight it would need.
+#[derive(Debug, PartialEq)]
+pub struct Imbalance<'a> {
+    pub program: &'a str,
+    pub weight: i32,
+    pub adjusted_weight: i32,
+}
+
+/// A tower of programs that keeps each subtree's total weight, and the set
+/// of programs whose children disagree, up to date across edits.
+pub struct Graph<'a> {
+    nodes: HashMap<&'a str, Node<'a>>,
+    root: &'a str,
+    unbalanced: HashSet<&'a str>,
+}
 
 impl<'a> Graph<'a> {
-    fn new(input: &'a str) -> Result<Self> {
+    pub fn new(input: &'a str) -> Result<Self> {
         match Self::parse_nodes(input) {
             Ok((_, nodes)) => {
-                let mut hashmap = HashMap::new();
-                for node in nodes {
-                    hashmap.insert(node.name, node);
-                }
-                Ok(Graph(hashmap))
+                let mut graph = Graph {
+                    nodes: nodes.into_iter().map(|node| (node.name, node)).collect(),
+                    root: "",
+                    unbalanced: HashSet::new(),
+                };
+                graph.link()?;
+                graph.root = graph.find_root()?;
+                graph.check_connected()?;
+                graph.compute_totals();
+                Ok(graph)
             }
             Err(e) => Err(anyhow!("Unable to parse graph: {}", e)),
         }
     }
 
-    fn parse_nodes(input: &'a str) -> IResult<&str, Vec<Node>> {
+    fn parse_nodes(input: &'a str) -> IResult<&'a str, Vec<Node<'a>>> {
         many1(terminated(Node::parse, opt(newline)))(input)
     }
 
-    fn root(&self) -> &str {
-        let all_nodes: HashSet<&str> = self.0.keys().copied().collect();
-        let mut childset = HashSet::new();
+    fn link(&mut self) -> Result<()> {
+        let edges: Vec<(&str, &str)> = self
+            .nodes
+            .values()
+            .flat_map(|node| node.children().iter().map(|&child| (node.name, child)))
+            .collect();
+
+        for (parent, child) in edges {
+            let node = self
+                .nodes
+                .get_mut(child)
+                .ok_or_else(|| anyhow!("{} has unknown child {}", parent, child))?;
+            if let Some(other) = node.parent {
+                bail!("{} has two parents: {} and {}", child, other, parent);
+            }
+            node.parent = Some(parent);
+        }
+
+        Ok(())
+    }
 
-        for (_, v) in self.0.iter() {
-            if let Some(ref children) = v.children {
-                for &child in children {
-                    childset.insert(child);
+    fn compute_totals(&mut self) {
+        let mut stack = vec![(self.root(), false)];
+
+        // post-order, so every child total is final before its parent's
+        while let Some((cur, seen)) = stack.pop() {
+            if !seen {
+                stack.push((cur, true));
+                for &child in self.nodes[cur].children() {
+                    stack.push((child, false));
                 }
             } else {
-                // leaf node
-                childset.insert(v.name);
+                let total = self.nodes[cur].weight
+                    + self.nodes[cur]
+                        .children()
+                        .iter()
+                        .map(|child| self.nodes[child].total_weight)
+                        .sum::<i32>();
+                self.nodes.get_mut(cur).unwrap().total_weight = total;
+                self.update_balance(cur);
             }
         }
+    }
 
-        let roots: Vec<_> = all_nodes.difference(&childset).copied().collect();
-        assert!(roots.len() == 1);
+    fn find_root(&self) -> Result<&'a str> {
+        let roots: Vec<_> = self
+            .nodes
+            .values()
+            .filter(|node| node.parent.is_none())
+            .map(|node| node.name)
+            .collect();
+
+        match roots[..] {
+            [root] => Ok(root),
+            _ => Err(anyhow!("Expected one root, found {}", roots.len())),
+        }
+    }
+
+    // One root doesn't make a tree: programs that hold each other up in a
+    // cycle all have parents, yet none of them is under the root.
+    fn check_connected(&self) -> Result<()> {
+        let mut reached = HashSet::new();
+        let mut stack = vec![self.root];
+        while let Some(cur) = stack.pop() {
+            reached.insert(cur);
+            stack.extend(self.nodes[cur].children());
+        }
 
-        roots[0]
+        match self
+            .nodes
+            .keys()
+            .filter(|name| !reached.contains(*name))
+            .min()
+        {
+            Some(name) => bail!("{} is not connected to the root {}", name, self.root),
+            None => Ok(()),
+        }
     }
 
-    fn adjusted_weight(&self) -> i32 {
-        let mut weights = HashMap::new();
-        let mut stack = Vec::new();
-        let root = self.0.get(self.root()).unwrap();
+    pub fn root(&self) -> &'a str {
+        self.root
+    }
 
-        // pass 1: compute weights
-        stack.push((root, false, None));
-        while let Some((cur, seen, parent)) = stack.pop() {
-            if !seen {
-                stack.push((cur, true, parent));
-                cur.parent = parent;
+    fn node(&self, name: &str) -> Result<&Node<'a>> {
+        self.nodes
+            .get(name)
+            .ok_or_else(|| anyhow!("No such program: {}", name))
+    }
 
-                if let Some(children) = &cur.children {
-                    for &child in children {
-                        stack.push((self.0.get_mut(child).unwrap(), false, Some(cur.name)));
-                    }
-                }
-            } else {
-                let mut weight = cur.weight;
-                if let Some(children) = &cur.children {
-                    for &child in children {
-                        cur.total_weight += self.0.get(child).unwrap().total_weight;
-                    }
-                }
-                weights.insert(cur.name, weight);
-            }
+    pub fn weight(&self, name: &str) -> Result<i32> {
+        Ok(self.node(name)?.weight)
+    }
+
+    pub fn total_weight(&self, name: &str) -> Result<i32> {
+        Ok(self.node(name)?.total_weight)
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.unbalanced.is_empty()
+    }
+
+    fn update_balance(&mut self, name: &'a str) {
+        let node = &self.nodes[name];
+        let mut totals = node
+            .children()
+            .iter()
+            .map(|child| self.nodes[child].total_weight);
+
+        let balanced = match totals.next() {
+            Some(first) => totals.all(|total| total == first),
+            None => true,
+        };
+
+        if balanced {
+            self.unbalanced.remove(name);
+        } else {
+            self.unbalanced.insert(name);
         }
+    }
 
-        println!("{:?}", weights);
+    // Add `delta` to the total of `start` and every program below it on the
+    // way to the root, rechecking the balance of each one we pass.
+    fn propagate(&mut self, start: &'a str, delta: i32) {
+        let mut cur = Some(start);
+        while let Some(name) = cur {
+            let node = self.nodes.get_mut(name).unwrap();
+            node.total_weight += delta;
+            cur = node.parent;
+            self.update_balance(name);
+        }
+    }
 
-        // pass 2: find the wrong weight
-        let mut target_weight = 0;
-        let mut cur: &Node = root;
+    pub fn set_weight(&mut self, name: &str, weight: i32) -> Result<()> {
+        let node = self
+            .nodes
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("No such program: {}", name))?;
+        let delta = weight - node.weight;
+        node.weight = weight;
+        let name = node.name;
+        self.propagate(name, delta);
+        Ok(())
+    }
 
-        loop {
-            let mut count_by_weight: HashMap<i32, Vec<&str>> = HashMap::new();
+    pub fn add_child(&mut self, parent: &'a str, name: &'a str, weight: i32) -> Result<()> {
+        self.node(parent)?;
+        if self.nodes.contains_key(name) {
+            bail!("Program already exists: {}", name);
+        }
 
-            for child in cur.children.as_ref().unwrap() {
-                let child_node = self.0.get(child).unwrap();
+        self.nodes.insert(
+            name,
+            Node {
+                name,
+                weight,
+                total_weight: weight,
+                parent: None,
+                children: None,
+            },
+        );
+        self.attach(parent, name);
+        Ok(())
+    }
+
+    /// Removes `name` and everything it holds up.
+    pub fn remove_child(&mut self, name: &str) -> Result<()> {
+        let node = self.node(name)?;
+        let name = node.name;
+        if node.parent.is_none() {
+            bail!("Cannot remove the root program {}", name);
+        }
+
+        self.detach(name);
+
+        let mut stack = vec![name];
+        while let Some(cur) = stack.pop() {
+            let node = self.nodes.remove(cur).unwrap();
+            self.unbalanced.remove(cur);
+            stack.extend(node.children());
+        }
+
+        Ok(())
+    }
+
+    pub fn reparent(&mut self, name: &str, new_parent: &str) -> Result<()> {
+        let node = self.node(name)?;
+        let name = node.name;
+        if node.parent.is_none() {
+            bail!("Cannot move the root program {}", name);
+        }
 
-                count_by_weight
-                    .entry(*weights.get(child_node.name).unwrap())
-                    .and_modify(|children| children.push(child_node.name))
-                    .or_insert_with(|| vec![child_node.name]);
+        let mut cur = Some(self.node(new_parent)?.name);
+        let new_parent = cur.unwrap();
+        while let Some(ancestor) = cur {
+            if ancestor == name {
+                bail!("Cannot move {} underneath itself", name);
             }
+            cur = self.nodes[ancestor].parent;
+        }
+
+        self.detach(name);
+        self.attach(new_parent, name);
+        Ok(())
+    }
 
-            // if all children are the same weight then we've found
-            // the node we need to adjust
-            if count_by_weight.keys().len() == 1 {
-                println!(
-                    "cur.weight={} target_weight={} weights(cur)={}",
-                    cur.weight,
-                    target_weight,
-                    weights.get(cur.name).unwrap()
-                );
-                return cur.weight + (target_weight - *weights.get(cur.name).unwrap());
+    fn attach(&mut self, parent: &'a str, name: &'a str) {
+        let node = self.nodes.get_mut(name).unwrap();
+        node.parent = Some(parent);
+        let total = node.total_weight;
+
+        self.nodes
+            .get_mut(parent)
+            .unwrap()
+            .children
+            .get_or_insert_with(Vec::new)
+            .push(name);
+        self.propagate(parent, total);
+    }
+
+    fn detach(&mut self, name: &'a str) {
+        let node = self.nodes.get_mut(name).unwrap();
+        let parent = node.parent.take().unwrap();
+        let total = node.total_weight;
+
+        let parent_node = self.nodes.get_mut(parent).unwrap();
+        if let Some(children) = parent_node.children.as_mut() {
+            children.retain(|&child| child != name);
+            if children.is_empty() {
+                parent_node.children = None;
             }
+        }
+        self.propagate(parent, -total);
+    }
 
-            // otherwise walk up the tree to the one that is different from the rest
-            for (k, v) in count_by_weight {
-                if v.len() == 1 {
-                    //wrong
-                    cur = self.0.get(v[0]).unwrap();
-                } else {
-                    // right, update target
-                    target_weight = k;
-                }
+    // Split the children of an unbalanced program into the one odd total
+    // and the total the rest agree on.
+    fn odd_child(&self, name: &str) -> Option<(&'a str, i32)> {
+        let mut count_by_weight: HashMap<i32, Vec<&str>> = HashMap::new();
+
+        for &child in self.nodes[name].children() {
+            count_by_weight
+                .entry(self.nodes[child].total_weight)
+                .or_default()
+                .push(child);
+        }
+
+        let (odd, rest): (Vec<_>, Vec<_>) = count_by_weight
+            .into_iter()
+            .partition(|(_, children)| children.len() == 1);
+
+        // with two children, or more than two distinct totals, there is
+        // no telling which one is wrong
+        match (&odd[..], &rest[..]) {
+            ([(_, odd)], [(target, _)]) => Some((odd[0], *target)),
+            _ => None,
+        }
+    }
+
+    fn depth(&self, name: &str) -> usize {
+        let mut depth = 0;
+        let mut cur = self.nodes[name].parent;
+        while let Some(parent) = cur {
+            depth += 1;
+            cur = self.nodes[parent].parent;
+        }
+        depth
+    }
+
+    // Follows odd children down from `cur` while they're unbalanced too.
+    fn resolve(&self, mut cur: &'a str) -> Option<Imbalance<'a>> {
+        loop {
+            let (odd, target) = self.odd_child(cur)?;
+
+            // keep descending while the odd one out is itself unbalanced
+            if self.unbalanced.contains(odd) {
+                cur = odd;
+                continue;
             }
+
+            let node = &self.nodes[odd];
+            return Some(Imbalance {
+                program: odd,
+                weight: node.weight,
+                adjusted_weight: node.weight + (target - node.total_weight),
+            });
         }
     }
+
+    /// The one weight change that would balance things, starting from the
+    /// shallowest unbalanced program (ties broken by name) and moving on to
+    /// the next whenever one can't be told apart.
+    pub fn imbalance(&self) -> Option<Imbalance<'a>> {
+        let mut candidates: Vec<_> = self.unbalanced.iter().copied().collect();
+        candidates.sort_by_key(|&name| (self.depth(name), name));
+        candidates.into_iter().find_map(|name| self.resolve(name))
+    }
 }
 
 #[aoc(day7, part1)]
@@ -173,13 +401,31 @@ pub fn solve_part1(input: &str) -> String {
 
 #[aoc(day7, part2)]
 pub fn solve_part2(input: &str) -> i32 {
-    Graph::new(input).unwrap().adjusted_weight()
+    Graph::new(input)
+        .unwrap()
+        .imbalance()
+        .unwrap()
+        .adjusted_weight
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &str = "pbga (66)\n\
+                           xhth (57)\n\
+                           ebii (61)\n\
+                           havc (66)\n\
+                           ktlj (57)\n\
+                           fwft (72) -> ktlj, cntj, xhth\n\
+                           qoyq (66)\n\
+                           padx (45) -> pbga, havc, qoyq\n\
+                           tknk (41) -> ugml, padx, fwft\n\
+                           jptl (61)\n\
+                           ugml (68) -> gyxo, ebii, jptl\n\
+                           gyxo (61)\n\
+                           cntj (57)";
+
     #[test]
     fn test_node_parse_name() {
         assert_eq!(Node::parse_name("abcd"), Ok(("", "abcd")));
@@ -216,47 +462,119 @@ mod tests {
     }
 
     #[test]
-    fn examples_part1() {
+    fn test_total_weight() {
+        let graph = Graph::new(EXAMPLE).unwrap();
+        assert_eq!(graph.total_weight("ugml").unwrap(), 251);
+        assert_eq!(graph.total_weight("padx").unwrap(), 243);
+        assert_eq!(graph.total_weight("fwft").unwrap(), 243);
+        assert!(!graph.is_balanced());
+    }
+
+    #[test]
+    fn test_set_weight() {
+        let mut graph = Graph::new(EXAMPLE).unwrap();
+        graph.set_weight("ugml", 60).unwrap();
+        assert_eq!(graph.total_weight("ugml").unwrap(), 243);
+        assert_eq!(graph.total_weight("tknk").unwrap(), 41 + 243 * 3);
+        assert!(graph.is_balanced());
+        assert_eq!(graph.imbalance(), None);
+
+        graph.set_weight("pbga", 70).unwrap();
         assert_eq!(
-            solve_part1(
-                "pbga (66)\n\
-         xhth (57)\n\
-         ebii (61)\n\
-         havc (66)\n\
-         ktlj (57)\n\
-         fwft (72) -> ktlj, cntj, xhth\n\
-         qoyq (66)\n\
-         padx (45) -> pbga, havc, qoyq\n\
-         tknk (41) -> ugml, padx, fwft\n\
-         jptl (61)\n\
-         ugml (68) -> gyxo, ebii, jptl\n\
-         gyxo (61)\n\
-         cntj (57)"
-            ),
-            "tknk"
+            graph.imbalance(),
+            Some(Imbalance {
+                program: "pbga",
+                weight: 70,
+                adjusted_weight: 66,
+            })
         );
+        assert!(graph.set_weight("nope", 1).is_err());
     }
 
     #[test]
-    fn examples_part2() {
+    fn test_add_remove_child() {
+        let mut graph = Graph::new(EXAMPLE).unwrap();
+        graph.set_weight("ugml", 60).unwrap();
+
+        graph.add_child("gyxo", "abcd", 3).unwrap();
+        assert_eq!(graph.total_weight("tknk").unwrap(), 41 + 243 * 3 + 3);
+        assert_eq!(
+            graph.imbalance(),
+            Some(Imbalance {
+                program: "gyxo",
+                weight: 61,
+                adjusted_weight: 58,
+            })
+        );
+        assert!(graph.add_child("gyxo", "abcd", 3).is_err());
+
+        graph.remove_child("abcd").unwrap();
+        assert!(graph.is_balanced());
+
+        graph.remove_child("padx").unwrap();
+        assert_eq!(graph.total_weight("tknk").unwrap(), 41 + 243 * 2);
+        assert!(graph.total_weight("pbga").is_err());
+        assert!(graph.remove_child("tknk").is_err());
+    }
+
+    #[test]
+    fn test_reparent() {
+        let mut graph = Graph::new(EXAMPLE).unwrap();
+        graph.reparent("pbga", "ugml").unwrap();
+        assert_eq!(graph.total_weight("padx").unwrap(), 243 - 66);
+        assert_eq!(graph.total_weight("ugml").unwrap(), 251 + 66);
+        assert_eq!(graph.total_weight("tknk").unwrap(), 41 + 251 + 243 * 2);
+        assert!(!graph.is_balanced());
+
+        graph.reparent("pbga", "padx").unwrap();
+        assert_eq!(graph.imbalance().unwrap().adjusted_weight, 60);
+
+        assert!(graph.reparent("padx", "pbga").is_err());
+        assert!(graph.reparent("tknk", "padx").is_err());
+    }
+
+    #[test]
+    fn test_imbalance_deterministic() {
+        // two unbalanced subtrees under a balanced root: one has only two
+        // children and can't be resolved, the other can
+        let input = "root (1) -> a, b\n\
+                     a (1) -> ax, ay\n\
+                     ax (5)\n\
+                     ay (6)\n\
+                     b (4) -> bx, by, bz\n\
+                     bx (3)\n\
+                     by (3)\n\
+                     bz (2)";
+        let graph = Graph::new(input).unwrap();
         assert_eq!(
-            solve_part2(
-                "pbga (66)\n\
-         xhth (57)\n\
-         ebii (61)\n\
-         havc (66)\n\
-         ktlj (57)\n\
-         fwft (72) -> ktlj, cntj, xhth\n\
-         qoyq (66)\n\
-         padx (45) -> pbga, havc, qoyq\n\
-         tknk (41) -> ugml, padx, fwft\n\
-         jptl (61)\n\
-         ugml (68) -> gyxo, ebii, jptl\n\
-         gyxo (61)\n\
-         cntj (57)"
-            ),
-            60
+            graph.total_weight("a").unwrap(),
+            graph.total_weight("b").unwrap()
         );
+        for _ in 0..10 {
+            assert_eq!(
+                Graph::new(input).unwrap().imbalance(),
+                Some(Imbalance {
+                    program: "bz",
+                    weight: 2,
+                    adjusted_weight: 3,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_cycle_rejected() {
+        let error = Graph::new("a (1) -> b\nb (1) -> a\nc (1)").err().unwrap();
+        assert_eq!(error.to_string(), "a is not connected to the root c");
+    }
+
+    #[test]
+    fn examples_part1() {
+        assert_eq!(solve_part1(EXAMPLE), "tknk");
+    }
+
+    #[test]
+    fn examples_part2() {
+        assert_eq!(solve_part2(EXAMPLE), 60);
     }
 }
-*/
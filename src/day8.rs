@@ -1,50 +1,204 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::ops::{Add, Sub};
+use std::fmt;
 
 use anyhow::{anyhow, Result};
 use nom::sequence::preceded;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{alpha1, i32, newline, space1},
+    character::complete::{alpha1, char, i32, newline, space0, space1},
     combinator::{map, opt},
-    multi::many1,
-    sequence::{terminated, tuple},
+    multi::{fold_many0, many1},
+    sequence::{delimited, pair, terminated, tuple},
     IResult,
 };
 
-type RegOp = fn(i32, i32) -> i32;
+pub mod analysis;
+pub mod bytecode;
+pub mod debugger;
+pub mod interval;
+pub mod minimize;
+pub mod trace;
+pub mod value;
 
-struct Instruction<'a> {
-    reg: &'a str,
-    op: RegOp,
-    operand: i32,
-    cond: Condition<'a>,
+use value::{Fault, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Inc,
+    Dec,
+    Mul,
+    Div,
+    Mod,
+    Set,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand<'a> {
+    Literal(i32),
+    Register(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction<'a> {
+    pub reg: &'a str,
+    pub op: Op,
+    pub operand: Operand<'a>,
+    pub cond: Condition<'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison<'a> {
+    pub reg: &'a str,
+    pub op: Cmp,
+    pub operand: Operand<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition<'a> {
+    Compare(Comparison<'a>),
+    Not(Box<Condition<'a>>),
+    And(Box<Condition<'a>>, Box<Condition<'a>>),
+    Or(Box<Condition<'a>>, Box<Condition<'a>>),
+}
+
+/// A line of a program: a register update, or one of the control flow
+/// forms. Jumps land on the line after their label.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement<'a> {
+    Update(Instruction<'a>),
+    Label(&'a str),
+    Jump {
+        label: &'a str,
+        cond: Option<Condition<'a>>,
+    },
+    Halt {
+        cond: Option<Condition<'a>>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecError {
+    DivideByZero { pc: usize },
+    Overflow { pc: usize },
+    StepLimit { limit: usize },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::DivideByZero { pc } => write!(f, "division by zero at instruction {}", pc),
+            ExecError::Overflow { pc } => write!(f, "arithmetic overflow at instruction {}", pc),
+            ExecError::StepLimit { limit } => {
+                write!(f, "program did not halt within {} steps", limit)
+            }
+        }
+    }
 }
 
-type OrdOp = fn(Ordering) -> bool;
+impl std::error::Error for ExecError {}
 
-#[derive(Debug, PartialEq)]
-struct Condition<'a> {
-    reg: &'a str,
-    op: OrdOp,
-    operand: i32,
+impl Fault {
+    fn at(self, pc: usize) -> ExecError {
+        match self {
+            Fault::DivideByZero => ExecError::DivideByZero { pc },
+            Fault::Overflow => ExecError::Overflow { pc },
+        }
+    }
+}
+
+/// Interpreter for the register language. Registers spring into existence
+/// at 0 the first time they are named, and hold a `V`; see `into_mode`.
+pub struct Cpu<'a, V = i32> {
+    inst: Vec<Statement<'a>>,
+    labels: HashMap<&'a str, usize>,
+    reg: HashMap<&'a str, V>,
+    pc: usize,
+    steps: usize,
+    step_limit: usize,
+    max: Option<V>,
+}
+
+// What a single step did: where it ran, whether its condition held, and
+// the register it changed with the values before and after.
+struct Outcome<'a, V> {
+    pc: usize,
+    taken: bool,
+    write: Option<(&'a str, V, V)>,
+}
+
+pub const DEFAULT_STEP_LIMIT: usize = 100_000_000;
+
+impl Op {
+    /// Combines the register's current value with the operand. `div` and
+    /// `mod` truncate toward zero.
+    pub fn apply<V: Value>(self, lhs: &V, rhs: &V) -> Result<V, Fault> {
+        let result = match self {
+            Op::Inc => lhs.add(rhs),
+            Op::Dec => lhs.sub(rhs),
+            Op::Mul => lhs.mul(rhs),
+            Op::Div | Op::Mod if rhs.is_zero() => return Err(Fault::DivideByZero),
+            Op::Div => lhs.div(rhs),
+            Op::Mod => lhs.rem(rhs),
+            Op::Set => Some(rhs.clone()),
+            Op::Min => Some(lhs.min(rhs).clone()),
+            Op::Max => Some(lhs.max(rhs).clone()),
+        };
+
+        result.ok_or(Fault::Overflow)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Inc => "inc",
+            Op::Dec => "dec",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Mod => "mod",
+            Op::Set => "set",
+            Op::Min => "min",
+            Op::Max => "max",
+        })
+    }
+}
+
+impl<'a> Operand<'a> {
+    pub fn parse(input: &'a str) -> IResult<&'a str, Self> {
+        alt((map(i32, Operand::Literal), map(alpha1, Operand::Register)))(input)
+    }
 }
 
-struct Cpu<'a> {
-    inst: Vec<Instruction<'a>>,
-    reg: HashMap<&'a str, i32>,
-    max: i32,
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Literal(val) => write!(f, "{}", val),
+            Operand::Register(reg) => f.write_str(reg),
+        }
+    }
 }
 
 impl<'a> Instruction<'a> {
-    fn parse(input: &'a str) -> IResult<&str, Self> {
+    pub fn parse(input: &'a str) -> IResult<&'a str, Self> {
         map(
             tuple((
                 terminated(alpha1, space1),
                 terminated(Self::parse_op, space1),
-                terminated(i32, space1),
+                terminated(Operand::parse, space1),
                 Condition::parse,
             )),
             |(reg, op, operand, cond)| Self {
@@ -56,91 +210,517 @@ impl<'a> Instruction<'a> {
         )(input)
     }
 
-    fn parse_op(input: &'a str) -> IResult<&str, RegOp> {
+    fn parse_op(input: &'a str) -> IResult<&'a str, Op> {
         alt((
-            map(tag("inc"), |_| i32::add as RegOp),
-            map(tag("dec"), |_| i32::sub as RegOp),
+            map(tag("inc"), |_| Op::Inc),
+            map(tag("dec"), |_| Op::Dec),
+            map(tag("mul"), |_| Op::Mul),
+            map(tag("div"), |_| Op::Div),
+            map(tag("mod"), |_| Op::Mod),
+            map(tag("set"), |_| Op::Set),
+            map(tag("min"), |_| Op::Min),
+            map(tag("max"), |_| Op::Max),
         ))(input)
     }
 }
 
-impl<'a> Condition<'a> {
-    pub fn parse(input: &'a str) -> IResult<&str, Self> {
-        let (input, _) = preceded(tag("if"), space1)(input)?;
+impl fmt::Display for Instruction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.reg, self.op, self.operand, self.cond)
+    }
+}
+
+impl Cmp {
+    /// The comparison that holds exactly when this one doesn't.
+    pub fn negate(self) -> Self {
+        match self {
+            Cmp::Gt => Cmp::Le,
+            Cmp::Ge => Cmp::Lt,
+            Cmp::Lt => Cmp::Ge,
+            Cmp::Le => Cmp::Gt,
+            Cmp::Eq => Cmp::Ne,
+            Cmp::Ne => Cmp::Eq,
+        }
+    }
+
+    pub fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Cmp::Gt => ordering.is_gt(),
+            Cmp::Ge => ordering.is_ge(),
+            Cmp::Lt => ordering.is_lt(),
+            Cmp::Le => ordering.is_le(),
+            Cmp::Eq => ordering.is_eq(),
+            Cmp::Ne => ordering.is_ne(),
+        }
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cmp::Gt => ">",
+            Cmp::Ge => ">=",
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Eq => "==",
+            Cmp::Ne => "!=",
+        })
+    }
+}
+
+impl<'a> Comparison<'a> {
+    pub fn parse(input: &'a str) -> IResult<&'a str, Self> {
         map(
             tuple((
                 terminated(alpha1, space1),
                 terminated(Self::parse_ordering, space1),
-                i32,
+                Operand::parse,
             )),
             |(reg, op, operand)| Self { reg, op, operand },
         )(input)
     }
 
-    fn parse_ordering(input: &'a str) -> IResult<&str, OrdOp> {
+    fn parse_ordering(input: &'a str) -> IResult<&'a str, Cmp> {
         alt((
-            map(tag(">="), |_| Ordering::is_ge as OrdOp),
-            map(tag("<="), |_| Ordering::is_le as OrdOp),
-            map(tag(">"), |_| Ordering::is_gt as OrdOp),
-            map(tag("<"), |_| Ordering::is_lt as OrdOp),
-            map(tag("=="), |_| Ordering::is_eq as OrdOp),
-            map(tag("!="), |_| Ordering::is_ne as OrdOp),
+            map(tag(">="), |_| Cmp::Ge),
+            map(tag("<="), |_| Cmp::Le),
+            map(tag(">"), |_| Cmp::Gt),
+            map(tag("<"), |_| Cmp::Lt),
+            map(tag("=="), |_| Cmp::Eq),
+            map(tag("!="), |_| Cmp::Ne),
         ))(input)
     }
 }
 
+impl fmt::Display for Comparison<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.reg, self.op, self.operand)
+    }
+}
+
+// Binding strength of each form, loosest first, used to decide where the
+// printer needs parentheses.
+const PREC_OR: u8 = 0;
+const PREC_AND: u8 = 1;
+const PREC_NOT: u8 = 2;
+const PREC_ATOM: u8 = 3;
+
+impl<'a> Condition<'a> {
+    pub fn parse(input: &'a str) -> IResult<&'a str, Self> {
+        let (input, _) = preceded(tag("if"), space1)(input)?;
+        Self::parse_or(input)
+    }
+
+    fn parse_or(input: &'a str) -> IResult<&'a str, Self> {
+        let (input, first) = Self::parse_and(input)?;
+        fold_many0(
+            preceded(tuple((space1, tag("or"), space1)), Self::parse_and),
+            move || first.clone(),
+            |lhs, rhs| Self::Or(Box::new(lhs), Box::new(rhs)),
+        )(input)
+    }
+
+    fn parse_and(input: &'a str) -> IResult<&'a str, Self> {
+        let (input, first) = Self::parse_not(input)?;
+        fold_many0(
+            preceded(tuple((space1, tag("and"), space1)), Self::parse_not),
+            move || first.clone(),
+            |lhs, rhs| Self::And(Box::new(lhs), Box::new(rhs)),
+        )(input)
+    }
+
+    fn parse_not(input: &'a str) -> IResult<&'a str, Self> {
+        alt((
+            map(
+                preceded(pair(tag("not"), space1), Self::parse_not),
+                |cond| Self::Not(Box::new(cond)),
+            ),
+            Self::parse_atom,
+        ))(input)
+    }
+
+    fn parse_atom(input: &'a str) -> IResult<&'a str, Self> {
+        alt((
+            delimited(
+                pair(char('('), space0),
+                Self::parse_or,
+                pair(space0, char(')')),
+            ),
+            map(Comparison::parse, Self::Compare),
+        ))(input)
+    }
+
+    /// Evaluates the condition left to right, only looking at the right
+    /// side of `and`/`or` when the left side doesn't settle it.
+    pub fn test<V: Ord>(&self, read: &mut impl FnMut(Operand<'a>) -> V) -> bool {
+        match self {
+            Self::Compare(cmp) => {
+                let lhs = read(Operand::Register(cmp.reg));
+                let rhs = read(cmp.operand);
+                cmp.op.test(lhs.cmp(&rhs))
+            }
+            Self::Not(cond) => !cond.test(read),
+            Self::And(lhs, rhs) => lhs.test(read) && rhs.test(read),
+            Self::Or(lhs, rhs) => lhs.test(read) || rhs.test(read),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Compare(_) => PREC_ATOM,
+            Self::Not(_) => PREC_NOT,
+            Self::And(..) => PREC_AND,
+            Self::Or(..) => PREC_OR,
+        }
+    }
+
+    // Both binary forms are left associative, so the right operand needs
+    // parentheses even at equal precedence to parse back the same way.
+    fn fmt_prec(&self, f: &mut fmt::Formatter<'_>, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            f.write_str("(")?;
+            self.fmt_prec(f, PREC_OR)?;
+            return f.write_str(")");
+        }
+
+        match self {
+            Self::Compare(cmp) => write!(f, "{}", cmp),
+            Self::Not(cond) => {
+                f.write_str("not ")?;
+                cond.fmt_prec(f, PREC_NOT)
+            }
+            Self::And(lhs, rhs) => {
+                lhs.fmt_prec(f, PREC_AND)?;
+                f.write_str(" and ")?;
+                rhs.fmt_prec(f, PREC_NOT)
+            }
+            Self::Or(lhs, rhs) => {
+                lhs.fmt_prec(f, PREC_OR)?;
+                f.write_str(" or ")?;
+                rhs.fmt_prec(f, PREC_AND)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("if ")?;
+        self.fmt_prec(f, PREC_OR)
+    }
+}
+
+impl<'a> Statement<'a> {
+    pub fn parse(input: &'a str) -> IResult<&'a str, Self> {
+        alt((
+            map(Instruction::parse, Self::Update),
+            map(terminated(alpha1, char(':')), Self::Label),
+            map(
+                pair(
+                    preceded(pair(tag("jmp"), space1), alpha1),
+                    opt(preceded(space1, Condition::parse)),
+                ),
+                |(label, cond)| Self::Jump { label, cond },
+            ),
+            map(
+                preceded(tag("halt"), opt(preceded(space1, Condition::parse))),
+                |cond| Self::Halt { cond },
+            ),
+        ))(input)
+    }
+}
+
+impl fmt::Display for Statement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cond = match self {
+            Statement::Update(inst) => return write!(f, "{}", inst),
+            Statement::Label(label) => return write!(f, "{}:", label),
+            Statement::Jump { label, cond } => {
+                write!(f, "jmp {}", label)?;
+                cond
+            }
+            Statement::Halt { cond } => {
+                f.write_str("halt")?;
+                cond
+            }
+        };
+
+        match cond {
+            Some(cond) => write!(f, " {}", cond),
+            None => Ok(()),
+        }
+    }
+}
+
 impl<'a> Cpu<'a> {
-    fn new(input: &'a str) -> Result<Self> {
-        match Self::parse_instructions(input) {
-            Ok((_, inst)) => {
-                let reg = HashMap::new();
-                Ok(Self {
-                    inst,
-                    reg,
-                    max: i32::MIN,
-                })
+    /// Parses a whole program. Anything but whitespace left over is an
+    /// error naming the line it starts on, rather than a shorter program.
+    pub fn new(input: &'a str) -> Result<Self> {
+        let rest = match Self::parse_instructions(input) {
+            Ok((rest, inst)) if rest.trim().is_empty() => {
+                return Self::with_instructions(inst);
+            }
+            Ok((rest, _)) => rest,
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e.input,
+            Err(e) => return Err(anyhow!("Unable to parse instructions: {}", e)),
+        };
+
+        let number = input[..input.len() - rest.len()].matches('\n').count() + 1;
+        let line = input.lines().nth(number - 1).unwrap_or_default();
+        Err(anyhow!("Unable to parse line {}: {}", number, line.trim()))
+    }
+
+    pub fn with_instructions(inst: Vec<Statement<'a>>) -> Result<Self> {
+        let mut labels = HashMap::new();
+        for (pc, stmt) in inst.iter().enumerate() {
+            if let Statement::Label(label) = stmt {
+                if labels.insert(*label, pc).is_some() {
+                    return Err(anyhow!("Duplicate label: {}", label));
+                }
+            }
+        }
+
+        for stmt in inst.iter() {
+            if let Statement::Jump { label, .. } = stmt {
+                if !labels.contains_key(label) {
+                    return Err(anyhow!("Jump to unknown label: {}", label));
+                }
             }
-            Err(e) => Err(anyhow!("Unable to parse instructions: {}", e)),
         }
+
+        Ok(Self {
+            inst,
+            labels,
+            reg: HashMap::new(),
+            pc: 0,
+            steps: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+            max: None,
+        })
+    }
+
+    pub fn parse_instructions(input: &'a str) -> IResult<&'a str, Vec<Statement<'a>>> {
+        many1(terminated(Statement::parse, opt(newline)))(input)
+    }
+
+    /// The canonical text of a program, one statement per line, with single
+    /// spaces and only the parentheses conditions need. Parsing it gives
+    /// back the same statements.
+    pub fn format_instructions(inst: &[Statement<'a>]) -> String {
+        inst.iter().map(|stmt| format!("{}\n", stmt)).collect()
+    }
+}
+
+impl<'a, V: Value> Cpu<'a, V> {
+    /// Switches the machine to another register type, keeping the program
+    /// and step limit but starting over from a clean slate.
+    pub fn into_mode<W: Value>(self) -> Cpu<'a, W> {
+        Cpu {
+            inst: self.inst,
+            labels: self.labels,
+            reg: HashMap::new(),
+            pc: 0,
+            steps: 0,
+            step_limit: self.step_limit,
+            max: None,
+        }
+    }
+
+    pub fn instructions(&self) -> &[Statement<'a>] {
+        &self.inst
+    }
+
+    /// Index of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.pc >= self.inst.len()
+    }
+
+    /// Number of instructions run since the last reset.
+    pub fn steps(&self) -> usize {
+        self.steps
     }
 
-    fn parse_instructions(input: &'a str) -> IResult<&str, Vec<Instruction>> {
-        many1(terminated(Instruction::parse, opt(newline)))(input)
+    /// Caps how many instructions may run before `step` gives up with
+    /// `ExecError::StepLimit`, so a program that loops forever still returns.
+    pub fn set_step_limit(&mut self, limit: usize) {
+        self.step_limit = limit;
     }
 
-    fn exec(&mut self) {
-        for inst in self.inst.iter() {
-            let cond_reg_val = self.reg.entry(inst.cond.reg).or_default().to_owned();
-            if (inst.cond.op)(cond_reg_val.cmp(&inst.cond.operand)) {
-                let reg_entry = self.reg.entry(inst.reg).or_default();
-                *reg_entry = (inst.op)(*reg_entry, inst.operand);
-                self.max = self.max.max(*reg_entry);
+    /// Runs the next instruction, returning `false` once the program has
+    /// halted.
+    pub fn step(&mut self) -> Result<bool, ExecError> {
+        Ok(self.step_inner(None)?.is_some())
+    }
+
+    // Runs the next instruction and reports what it did, pushing every
+    // register it looked at onto `reads` when given.
+    fn step_inner(
+        &mut self,
+        mut reads: Option<&mut Vec<(&'a str, V)>>,
+    ) -> Result<Option<Outcome<'a, V>>, ExecError> {
+        let Some(stmt) = self.inst.get(self.pc) else {
+            return Ok(None);
+        };
+
+        if self.steps >= self.step_limit {
+            return Err(ExecError::StepLimit {
+                limit: self.step_limit,
+            });
+        }
+
+        let reg = &mut self.reg;
+        let mut read = |operand| Self::read(reg, &mut reads, operand);
+
+        let pc = self.pc;
+        let mut next = pc + 1;
+        let mut taken = true;
+        let mut write = None;
+        match stmt {
+            Statement::Update(inst) => {
+                taken = inst.cond.test(&mut read);
+                if taken {
+                    write =
+                        Some(Self::update(reg, &mut reads, inst).map_err(|fault| fault.at(pc))?);
+                }
             }
+            Statement::Label(_) => {}
+            Statement::Jump { label, cond } => {
+                taken = cond.as_ref().is_none_or(|cond| cond.test(&mut read));
+                if taken {
+                    next = self.labels[label] + 1;
+                }
+            }
+            Statement::Halt { cond } => {
+                taken = cond.as_ref().is_none_or(|cond| cond.test(&mut read));
+                if taken {
+                    next = self.inst.len();
+                }
+            }
+        }
+
+        if let Some((_, _, after)) = &write {
+            self.track_max(after.clone());
         }
+
+        self.pc = next;
+        self.steps += 1;
+        Ok(Some(Outcome { pc, taken, write }))
     }
 
-    fn largest_register(&self) -> Option<i32> {
-        self.reg.values().copied().max()
+    fn update(
+        reg: &mut HashMap<&'a str, V>,
+        reads: &mut Option<&mut Vec<(&'a str, V)>>,
+        inst: &Instruction<'a>,
+    ) -> Result<(&'a str, V, V), Fault> {
+        let operand = Self::read(reg, reads, inst.operand);
+        let reg_entry = reg.entry(inst.reg).or_default();
+        let before = reg_entry.clone();
+        *reg_entry = inst.op.apply(reg_entry, &operand)?;
+        Ok((inst.reg, before, reg_entry.clone()))
+    }
+
+    /// Runs one instruction against the registers, outside the program and
+    /// without moving the program counter. Returns whether its condition
+    /// held.
+    pub fn eval(&mut self, inst: &Instruction<'a>) -> Result<bool, Fault> {
+        let reg = &mut self.reg;
+        let mut reads = None;
+        if !inst
+            .cond
+            .test(&mut |operand| Self::read(reg, &mut reads, operand))
+        {
+            return Ok(false);
+        }
+
+        let (_, _, after) = Self::update(reg, &mut reads, inst)?;
+        self.track_max(after);
+        Ok(true)
+    }
+
+    fn read(
+        reg: &mut HashMap<&'a str, V>,
+        reads: &mut Option<&mut Vec<(&'a str, V)>>,
+        operand: Operand<'a>,
+    ) -> V {
+        match operand {
+            Operand::Literal(val) => V::from_i32(val),
+            Operand::Register(name) => {
+                let val = reg.entry(name).or_default().clone();
+                if let Some(reads) = reads {
+                    reads.push((name, val.clone()));
+                }
+                val
+            }
+        }
+    }
+
+    pub fn exec(&mut self) -> Result<(), ExecError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.reg.clear();
+        self.pc = 0;
+        self.steps = 0;
+        self.max = None;
+    }
+
+    pub fn get(&self, reg: &str) -> V {
+        self.reg.get(reg).cloned().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, reg: &'a str, val: V) {
+        self.reg.insert(reg, val.clone());
+        self.track_max(val);
+    }
+
+    pub fn registers(&self) -> &HashMap<&'a str, V> {
+        &self.reg
+    }
+
+    fn track_max(&mut self, val: V) {
+        self.max = Some(match self.max.take() {
+            Some(max) => max.max(val),
+            None => val,
+        });
+    }
+
+    /// The highest value any register has held so far.
+    pub fn max(&self) -> Option<V> {
+        self.max.clone()
+    }
+
+    pub fn largest_register(&self) -> Option<V> {
+        self.reg.values().max().cloned()
     }
 }
 
 #[aoc(day8, part1)]
 pub fn solve_part1(input: &str) -> i32 {
     let mut cpu = Cpu::new(input).unwrap();
-    cpu.exec();
+    cpu.exec().unwrap();
     cpu.largest_register().unwrap()
 }
 
 #[aoc(day8, part2)]
 pub fn solve_part2(input: &str) -> i32 {
     let mut cpu = Cpu::new(input).unwrap();
-    cpu.exec();
-    cpu.max
+    cpu.exec().unwrap();
+    // nothing ever written leaves the old `i32::MIN` starting value
+    cpu.max().unwrap_or(i32::MIN)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::lcg;
+    use num_bigint::BigInt;
+    use std::num::Saturating;
 
     #[test]
     fn test_parse_condition_greater_than() {
@@ -148,11 +728,11 @@ mod tests {
             Condition::parse("if a > 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
                     reg: "a",
-                    op: Ordering::is_gt as OrdOp,
-                    operand: 1,
-                }
+                    op: Cmp::Gt,
+                    operand: Operand::Literal(1),
+                })
             ))
         );
     }
@@ -163,11 +743,11 @@ mod tests {
             Condition::parse("if a < 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
                     reg: "a",
-                    op: Ordering::is_lt as OrdOp,
-                    operand: 1,
-                }
+                    op: Cmp::Lt,
+                    operand: Operand::Literal(1),
+                })
             ))
         );
     }
@@ -178,11 +758,11 @@ mod tests {
             Condition::parse("if a >= 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
                     reg: "a",
-                    op: Ordering::is_ge as OrdOp,
-                    operand: 1,
-                }
+                    op: Cmp::Ge,
+                    operand: Operand::Literal(1),
+                })
             ))
         );
     }
@@ -193,11 +773,11 @@ mod tests {
             Condition::parse("if a <= 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
                     reg: "a",
-                    op: Ordering::is_le as OrdOp,
-                    operand: 1,
-                }
+                    op: Cmp::Le,
+                    operand: Operand::Literal(1),
+                })
             ))
         );
     }
@@ -208,11 +788,11 @@ mod tests {
             Condition::parse("if a == 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
                     reg: "a",
-                    op: Ordering::is_eq as OrdOp,
-                    operand: 1,
-                }
+                    op: Cmp::Eq,
+                    operand: Operand::Literal(1),
+                })
             ))
         );
     }
@@ -223,13 +803,426 @@ mod tests {
             Condition::parse("if a != 1"),
             Ok((
                 "",
-                Condition {
+                Condition::Compare(Comparison {
+                    reg: "a",
+                    op: Cmp::Ne,
+                    operand: Operand::Literal(1),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_condition_register_operand() {
+        assert_eq!(
+            Condition::parse("if c > d"),
+            Ok((
+                "",
+                Condition::Compare(Comparison {
+                    reg: "c",
+                    op: Cmp::Gt,
+                    operand: Operand::Register("d"),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_condition_compound() {
+        let cmp = |reg, op, val| {
+            Box::new(Condition::Compare(Comparison {
+                reg,
+                op,
+                operand: Operand::Literal(val),
+            }))
+        };
+
+        assert_eq!(
+            Condition::parse("if b > 1 and (c == 0 or d != 3)"),
+            Ok((
+                "",
+                Condition::And(
+                    cmp("b", Cmp::Gt, 1),
+                    Box::new(Condition::Or(cmp("c", Cmp::Eq, 0), cmp("d", Cmp::Ne, 3)))
+                )
+            ))
+        );
+
+        assert_eq!(
+            Condition::parse("if not a < 1 or b > 2 and c > 3"),
+            Ok((
+                "",
+                Condition::Or(
+                    Box::new(Condition::Not(cmp("a", Cmp::Lt, 1))),
+                    Box::new(Condition::And(cmp("b", Cmp::Gt, 2), cmp("c", Cmp::Gt, 3)))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_display_condition_compound() {
+        for line in [
+            "if a > 1 and b > 2 and c > 3",
+            "if a > 1 and (b > 2 and c > 3)",
+            "if a > 1 or b > 2 and c > 3",
+            "if (a > 1 or b > 2) and c > 3",
+            "if not (a > 1 or b > 2)",
+            "if not not a > 1",
+        ] {
+            let (_, cond) = Condition::parse(line).unwrap();
+            assert_eq!(cond.to_string(), line);
+        }
+
+        let (_, cond) = Condition::parse("if ( ( a > 1 ) )").unwrap();
+        assert_eq!(cond.to_string(), "if a > 1");
+    }
+
+    #[test]
+    fn test_condition_short_circuit() {
+        let mut cpu = Cpu::new(
+            "a inc 5 if b > 1 and c == 0\n\
+             a inc 1 if b == 0 or c == 0\n\
+             a inc 2 if not (d > 0 or e > 0)",
+        )
+        .unwrap();
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), 3);
+
+        // c only ever appears after the outcome was already settled
+        let mut regs: Vec<_> = cpu.registers().keys().copied().collect();
+        regs.sort();
+        assert_eq!(regs, vec!["a", "b", "d", "e"]);
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!(
+            Instruction::parse("a mul b if c != -2"),
+            Ok((
+                "",
+                Instruction {
                     reg: "a",
-                    op: Ordering::is_ne as OrdOp,
-                    operand: 1,
+                    op: Op::Mul,
+                    operand: Operand::Register("b"),
+                    cond: Condition::Compare(Comparison {
+                        reg: "c",
+                        op: Cmp::Ne,
+                        operand: Operand::Literal(-2),
+                    }),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_display_instruction() {
+        for line in [
+            "a inc 5 if b > 1",
+            "a dec -3 if b <= c",
+            "a mul b if c >= 0",
+            "a div 2 if b == 0",
+            "a mod 7 if b != 1",
+            "a set b if c < d",
+            "a min -4 if a > 0",
+            "a max b if a < 0",
+        ] {
+            let (_, inst) = Instruction::parse(line).unwrap();
+            assert_eq!(inst.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_extended_ops() {
+        let mut cpu = Cpu::new(
+            "a set 17 if a == 0\n\
+             b inc a if a > 0\n\
+             b mul 3 if b == a\n\
+             c set b if c < b\n\
+             c div -4 if c > 0\n\
+             b mod 10 if c < 0\n\
+             a min c if a > c\n\
+             d max b if d == 0",
+        )
+        .unwrap();
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), -12);
+        assert_eq!(cpu.get("b"), 1);
+        assert_eq!(cpu.get("c"), -12);
+        assert_eq!(cpu.get("d"), 1);
+        assert_eq!(cpu.max(), Some(51));
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        let mut cpu = Cpu::new("a inc 1 if a == 0\nb div c if a > 0\na inc 1 if a > 0").unwrap();
+        assert_eq!(cpu.exec(), Err(ExecError::DivideByZero { pc: 1 }));
+        assert_eq!(cpu.pc(), 1);
+        assert_eq!(cpu.get("b"), 0);
+
+        let mut cpu = Cpu::new("b mod 0 if a == 0").unwrap();
+        assert_eq!(cpu.exec(), Err(ExecError::DivideByZero { pc: 0 }));
+        assert_eq!(Op::Div.apply(&7, &0), Err(Fault::DivideByZero));
+        assert_eq!(Op::Mod.apply(&7, &-3), Ok(1));
+    }
+
+    const DOUBLING: &str = "a set 1 if a == 0\n\
+                            top:\n\
+                            a mul 2 if a > 0\n\
+                            n inc 1 if a > 0\n\
+                            jmp top if n < 40";
+
+    #[test]
+    fn test_overflow_checked() {
+        let mut cpu = Cpu::new(DOUBLING).unwrap();
+        assert_eq!(cpu.exec(), Err(ExecError::Overflow { pc: 2 }));
+        assert_eq!(cpu.get("a"), 1 << 30);
+        assert_eq!(cpu.get("n"), 30);
+
+        let bytecode = bytecode::Bytecode::new(DOUBLING).unwrap();
+        assert_eq!(
+            bytecode.run::<i32>(DEFAULT_STEP_LIMIT).map(|_| ()),
+            Err(ExecError::Overflow { pc: 2 })
+        );
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut cpu = Cpu::new(DOUBLING).unwrap().into_mode::<Saturating<i32>>();
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), Saturating(i32::MAX));
+
+        let mut cpu = Cpu::new(DOUBLING).unwrap().into_mode::<i64>();
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), 1 << 40);
+        assert_eq!(cpu.max(), Some(1 << 40));
+
+        let mut cpu = Cpu::new(DOUBLING).unwrap().into_mode::<BigInt>();
+        cpu.set("a", BigInt::from(1) << 100);
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), BigInt::from(1) << 140);
+
+        let regs = bytecode::Bytecode::new(DOUBLING)
+            .unwrap()
+            .run::<BigInt>(DEFAULT_STEP_LIMIT)
+            .unwrap();
+        assert_eq!(regs.largest_register(), Some(BigInt::from(1) << 40));
+    }
+
+    #[test]
+    fn test_parse_statement() {
+        assert_eq!(Statement::parse("top:"), Ok(("", Statement::Label("top"))));
+        assert_eq!(
+            Statement::parse("halt"),
+            Ok(("", Statement::Halt { cond: None }))
+        );
+        assert_eq!(
+            Statement::parse("jmp top"),
+            Ok((
+                "",
+                Statement::Jump {
+                    label: "top",
+                    cond: None
                 }
             ))
         );
+
+        // registers may still be called jmp or halt
+        assert!(matches!(
+            Statement::parse("jmp inc 1 if halt > 0"),
+            Ok(("", Statement::Update(_)))
+        ));
+
+        for line in [
+            "top:",
+            "halt",
+            "halt if a > 3",
+            "jmp top",
+            "jmp top if x > 0",
+        ] {
+            let (_, stmt) = Statement::parse(line).unwrap();
+            assert_eq!(stmt.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_format_instructions() {
+        let (_, program) = Cpu::parse_instructions(
+            "top:\n\
+             a inc 5 if ( b > 1 )\n\
+             a dec -3 if (b <= c and ((c > 0)))\n\
+             jmp top if (a < 10 or b > 0) and not (c == 1)\n\
+             halt",
+        )
+        .unwrap();
+        let text = Cpu::format_instructions(&program);
+
+        assert_eq!(
+            text,
+            "top:\n\
+             a inc 5 if b > 1\n\
+             a dec -3 if b <= c and c > 0\n\
+             jmp top if (a < 10 or b > 0) and not c == 1\n\
+             halt\n"
+        );
+        assert_eq!(Cpu::parse_instructions(&text), Ok(("", program)));
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let names = ["a", "b", "c", "jmp", "halt"];
+        let labels = ["top", "end"];
+        let ops = [
+            Op::Inc,
+            Op::Dec,
+            Op::Mul,
+            Op::Div,
+            Op::Mod,
+            Op::Set,
+            Op::Min,
+            Op::Max,
+        ];
+        let cmps = [Cmp::Gt, Cmp::Ge, Cmp::Lt, Cmp::Le, Cmp::Eq, Cmp::Ne];
+
+        // like the bytecode tests, but building statements directly so the
+        // printer sees shapes no hand-written input has
+        let mut next = lcg(6789);
+
+        fn operand<'a>(next: &mut impl FnMut(usize) -> usize, names: &[&'a str]) -> Operand<'a> {
+            match next(2) {
+                0 => Operand::Literal(next(2001) as i32 - 1000),
+                _ => Operand::Register(names[next(names.len())]),
+            }
+        }
+
+        fn condition<'a>(
+            next: &mut impl FnMut(usize) -> usize,
+            names: &[&'a str],
+            cmps: &[Cmp],
+            depth: usize,
+        ) -> Condition<'a> {
+            let sub = |next: &mut _| Box::new(condition(next, names, cmps, depth - 1));
+            match if depth == 0 { 0 } else { next(4) } {
+                0 => Condition::Compare(Comparison {
+                    reg: names[next(names.len())],
+                    op: cmps[next(cmps.len())],
+                    operand: operand(next, names),
+                }),
+                1 => Condition::Not(sub(next)),
+                2 => Condition::And(sub(next), sub(next)),
+                _ => Condition::Or(sub(next), sub(next)),
+            }
+        }
+
+        let mut program = Vec::new();
+        for _ in 0..500 {
+            let cond = condition(&mut next, &names, &cmps, 4);
+            program.push(match next(8) {
+                0 => Statement::Label(labels[next(labels.len())]),
+                1 => Statement::Jump {
+                    label: labels[next(labels.len())],
+                    cond: (next(2) == 0).then_some(cond),
+                },
+                2 => Statement::Halt {
+                    cond: (next(2) == 0).then_some(cond),
+                },
+                _ => Statement::Update(Instruction {
+                    reg: names[next(names.len())],
+                    op: ops[next(ops.len())],
+                    operand: operand(&mut next, &names),
+                    cond,
+                }),
+            });
+        }
+
+        let text = Cpu::format_instructions(&program);
+        assert_eq!(Cpu::parse_instructions(&text), Ok(("", program)));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let mut cpu = Cpu::new(
+            "x set 10 if x == 0\n\
+             top:\n\
+             sum inc x if x > 0\n\
+             x dec 1 if x > 0\n\
+             halt if sum > 50\n\
+             jmp top if x > 0\n\
+             sum set -1 if x == 0",
+        )
+        .unwrap();
+        cpu.exec().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get("sum"), 10 + 9 + 8 + 7 + 6 + 5 + 4 + 3);
+        assert_eq!(cpu.get("x"), 2);
+        assert_eq!(cpu.steps(), 1 + 1 + 7 * 4 + 3);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut cpu = Cpu::new("top:\na inc 1 if a >= 0\njmp top").unwrap();
+        cpu.set_step_limit(30);
+        assert_eq!(cpu.exec(), Err(ExecError::StepLimit { limit: 30 }));
+        assert_eq!(cpu.steps(), 30);
+        assert_eq!(cpu.get("a"), 15);
+
+        cpu.reset();
+        assert_eq!(cpu.steps(), 0);
+        assert!(cpu.step().unwrap());
+    }
+
+    #[test]
+    fn test_bad_labels() {
+        assert!(Cpu::new("jmp nowhere").is_err());
+        assert_eq!(
+            Cpu::new("a inc 1 if b > 0\na inc if b > 0\nb inc 1 if a > 0")
+                .err()
+                .unwrap()
+                .to_string(),
+            "Unable to parse line 2: a inc if b > 0"
+        );
+        assert_eq!(
+            Cpu::new("bogus").err().unwrap().to_string(),
+            "Unable to parse line 1: bogus"
+        );
+        assert!(Cpu::new("a inc 1 if b > 0\n\n").is_ok());
+        assert!(Cpu::new("top:\na inc 1 if a > 0\ntop:").is_err());
+    }
+
+    #[test]
+    fn test_cpu_step() {
+        let mut cpu = Cpu::new("a inc 2 if b == 0\nb dec 3 if a > 1").unwrap();
+        assert_eq!(cpu.instructions().len(), 2);
+        assert_eq!(cpu.max(), None);
+
+        assert!(cpu.step().unwrap());
+        assert_eq!(cpu.pc(), 1);
+        assert_eq!(cpu.get("a"), 2);
+        assert_eq!(cpu.get("b"), 0);
+
+        assert!(cpu.step().unwrap());
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get("b"), -3);
+        assert_eq!(cpu.max(), Some(2));
+        assert!(!cpu.step().unwrap());
+    }
+
+    #[test]
+    fn test_cpu_set_and_reset() {
+        let mut cpu = Cpu::new("a inc 2 if b == 0\nb dec 3 if a > 1").unwrap();
+        cpu.set("b", 7);
+        cpu.exec().unwrap();
+        assert_eq!(cpu.get("a"), 0);
+        assert_eq!(cpu.get("b"), 7);
+        assert_eq!(cpu.max(), Some(7));
+
+        cpu.reset();
+        assert!(cpu.registers().is_empty());
+        assert_eq!(cpu.pc(), 0);
+        assert_eq!(cpu.max(), None);
+
+        cpu.exec().unwrap();
+        assert_eq!(cpu.largest_register(), Some(2));
     }
 
     #[test]
@@ -256,5 +1249,6 @@ mod tests {
             ),
             10
         );
+        assert_eq!(solve_part2("a inc 1 if b > 0"), i32::MIN);
     }
 }
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::interval::Bounds;
+use super::{Condition, Op, Operand, Statement};
+
+/// Something suspicious about a program, found without running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint<'a> {
+    /// The register is updated, but nothing ever looks at it.
+    NeverRead(&'a str),
+    /// Nothing earlier in the program writes the register, so this read
+    /// sees the default of 0.
+    ReadBeforeWrite(&'a str),
+    /// No values the registers can hold at this line make the condition
+    /// true, so the line never takes effect.
+    NeverTrue,
+    /// Taking effect wouldn't change anything, e.g. `inc 0` or `mul 1`.
+    NoEffect,
+}
+
+impl fmt::Display for Lint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::NeverRead(reg) => write!(f, "register `{}` is written but never read", reg),
+            Lint::ReadBeforeWrite(reg) => {
+                write!(f, "register `{}` is read before it is written", reg)
+            }
+            Lint::NeverTrue => f.write_str("condition can never be true"),
+            Lint::NoEffect => f.write_str("statement has no effect"),
+        }
+    }
+}
+
+/// A lint, and the 1-based line of the program it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic<'a> {
+    pub line: usize,
+    pub lint: Lint<'a>,
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.lint)
+    }
+}
+
+fn condition_reads<'a>(cond: &Condition<'a>, reads: &mut Vec<&'a str>) {
+    match cond {
+        Condition::Compare(cmp) => {
+            reads.push(cmp.reg);
+            if let Operand::Register(reg) = cmp.operand {
+                reads.push(reg);
+            }
+        }
+        Condition::Not(cond) => condition_reads(cond, reads),
+        Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+            condition_reads(lhs, reads);
+            condition_reads(rhs, reads);
+        }
+    }
+}
+
+// Registers a statement looks at. An update reading its own register to
+// change it doesn't count; the point is whether a value is ever used.
+fn reads<'a>(stmt: &Statement<'a>) -> Vec<&'a str> {
+    let mut reads = Vec::new();
+    match stmt {
+        Statement::Update(inst) => {
+            condition_reads(&inst.cond, &mut reads);
+            if let Operand::Register(reg) = inst.operand {
+                reads.push(reg);
+            }
+        }
+        Statement::Jump {
+            cond: Some(cond), ..
+        }
+        | Statement::Halt { cond: Some(cond) } => condition_reads(cond, &mut reads),
+        _ => {}
+    }
+
+    reads
+}
+
+fn condition<'s, 'a>(stmt: &'s Statement<'a>) -> Option<&'s Condition<'a>> {
+    match stmt {
+        Statement::Update(inst) => Some(&inst.cond),
+        Statement::Jump { cond, .. } | Statement::Halt { cond } => cond.as_ref(),
+        Statement::Label(_) => None,
+    }
+}
+
+fn no_effect(program: &[Statement], pc: usize) -> bool {
+    match &program[pc] {
+        Statement::Update(inst) => match (inst.op, inst.operand) {
+            (Op::Inc | Op::Dec, Operand::Literal(0)) => true,
+            (Op::Mul | Op::Div, Operand::Literal(1)) => true,
+            (Op::Set | Op::Min | Op::Max, Operand::Register(reg)) => reg == inst.reg,
+            _ => false,
+        },
+        Statement::Jump { label, .. } => program.get(pc + 1) == Some(&Statement::Label(label)),
+        _ => false,
+    }
+}
+
+/// Checks `program` for likely mistakes, returning diagnostics ordered by
+/// line. Reads and writes are matched up in program order, ignoring
+/// updates that can never happen. Value ranges come from `Bounds`, which
+/// gives up on registers written anywhere in a program with jumps.
+pub fn analyze<'a>(program: &[Statement<'a>]) -> Vec<Diagnostic<'a>> {
+    let mut diagnostics = Vec::new();
+    let mut written = HashMap::new();
+    let mut read = HashMap::new();
+    let bounds = Bounds::new(program);
+
+    for (pc, stmt) in program.iter().enumerate() {
+        let line = pc + 1;
+
+        for reg in reads(stmt) {
+            if !written.contains_key(reg) && !read.contains_key(reg) {
+                diagnostics.push(Diagnostic {
+                    line,
+                    lint: Lint::ReadBeforeWrite(reg),
+                });
+            }
+            read.entry(reg).or_insert(line);
+        }
+
+        // lines that can't be reached at all aren't this lint's business
+        let possible = match (condition(stmt), bounds.before(pc)) {
+            (Some(cond), Some(env)) => env.test(cond).0,
+            _ => true,
+        };
+        if !possible {
+            diagnostics.push(Diagnostic {
+                line,
+                lint: Lint::NeverTrue,
+            });
+        }
+
+        if no_effect(program, pc) {
+            diagnostics.push(Diagnostic {
+                line,
+                lint: Lint::NoEffect,
+            });
+        }
+
+        if let (Statement::Update(inst), true) = (stmt, possible) {
+            written.entry(inst.reg).or_insert(line);
+        }
+    }
+
+    for (reg, line) in written {
+        if !read.contains_key(reg) {
+            diagnostics.push(Diagnostic {
+                line,
+                lint: Lint::NeverRead(reg),
+            });
+        }
+    }
+
+    // stable, so lints on the same line keep the order they were found in
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day8::Cpu;
+
+    fn lint(input: &str) -> Vec<String> {
+        let (_, program) = Cpu::parse_instructions(input).unwrap();
+        analyze(&program).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_example() {
+        assert_eq!(
+            lint(
+                "b inc 5 if a > 1\n\
+                 a inc 1 if b < 5\n\
+                 c dec -10 if a >= 1\n\
+                 c inc -20 if c == 10"
+            ),
+            vec![
+                "line 1: register `a` is read before it is written",
+                "line 1: condition can never be true",
+                "line 2: register `b` is read before it is written",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_never_read() {
+        assert_eq!(
+            lint(
+                "a inc 1 if b == 0\n\
+                 b inc 1 if a == 1\n\
+                 c inc 1 if a == 1"
+            ),
+            vec![
+                "line 1: register `b` is read before it is written",
+                "line 3: register `c` is written but never read",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_never_true() {
+        assert_eq!(
+            lint(
+                "a inc 5 if a == 0\n\
+                 b inc 1 if a > 5 or a < 5\n\
+                 b inc 1 if not a != 5\n\
+                 b inc 1 if a == 5 and b == 1\n\
+                 halt if b > a"
+            ),
+            vec![
+                "line 1: register `a` is read before it is written",
+                "line 2: condition can never be true",
+                "line 5: condition can never be true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_effect() {
+        assert_eq!(
+            lint(
+                "a inc 0 if a == 0\n\
+                 a mul 1 if a == 0\n\
+                 a max a if a == 0\n\
+                 jmp end if a == 0\n\
+                 end:"
+            ),
+            vec![
+                "line 1: register `a` is read before it is written",
+                "line 1: statement has no effect",
+                "line 2: statement has no effect",
+                "line 3: statement has no effect",
+                "line 4: statement has no effect",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jumps_widen() {
+        // the loop could run any number of times, so a > 100 is possible
+        assert_eq!(
+            lint(
+                "top:\n\
+                 a inc 1 if a < 3\n\
+                 jmp top if a < 3\n\
+                 b inc 1 if a > 100\n\
+                 a inc 1 if b > 0"
+            ),
+            vec!["line 2: register `a` is read before it is written"]
+        );
+    }
+}
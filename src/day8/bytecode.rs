@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use anyhow::Result;
+
+use super::value::Value;
+use super::{Cmp, Condition, Cpu, ExecError, Op, Operand, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Src {
+    Reg(u32),
+    // index into the constant pool
+    Imm(u32),
+}
+
+// Branch and jump targets are code offsets. While compiling, one aimed at
+// a label not placed yet holds the label id until it's patched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Code {
+    Apply {
+        dst: u32,
+        op: Op,
+        src: Src,
+    },
+    Branch {
+        lhs: u32,
+        cmp: Cmp,
+        rhs: Src,
+        target: u32,
+    },
+    Jump {
+        target: u32,
+    },
+}
+
+/// A program lowered to flat code over numbered registers, for running
+/// large programs without hashing a register name on every access.
+///
+/// Compiling costs less than interpreting the program once, so even a
+/// straight-line program comes out ahead compiled and run, and anything
+/// that loops far more so (`cargo bench --bench day8`).
+pub struct Bytecode<'a> {
+    code: Vec<Code>,
+    // the code offset each statement starts at, for error reporting
+    starts: Vec<u32>,
+    names: Vec<&'a str>,
+    index: Index<'a>,
+    consts: Vec<i32>,
+}
+
+/// Register file left behind by a bytecode run.
+pub struct Registers<'a, V = i32> {
+    names: Vec<&'a str>,
+    index: Index<'a>,
+    values: Vec<V>,
+    touched: Vec<bool>,
+    max: Option<V>,
+}
+
+// Register names are runs of letters, mostly short ones, and the compiler
+// looks one up for every operand. Up to ten letters pack six bits each
+// into a `u64`, which hashes and compares far quicker than the string.
+fn pack(name: &str) -> Option<u64> {
+    if name.len() > 10 {
+        return None;
+    }
+    name.bytes().try_fold(0, |key, byte| {
+        byte.is_ascii_alphabetic()
+            .then(|| key << 6 | u64::from(byte & 0x3f))
+    })
+}
+
+// Hashes a packed name with one multiply, folding the well-mixed high
+// bits down to where the table looks for its bucket.
+#[derive(Default)]
+struct Packed(u64);
+
+impl Hasher for Packed {
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("only packed names are hashed");
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        let mixed = key.wrapping_mul(0x9e3779b97f4a7c15);
+        self.0 = mixed ^ mixed >> 32;
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Register numbers by name.
+#[derive(Debug, Clone, Default)]
+struct Index<'a> {
+    packed: HashMap<u64, u32, BuildHasherDefault<Packed>>,
+    long: HashMap<&'a str, u32>,
+}
+
+impl<'a> Index<'a> {
+    fn get(&self, name: &str) -> Option<u32> {
+        match pack(name) {
+            Some(key) => self.packed.get(&key).copied(),
+            None => self.long.get(name).copied(),
+        }
+    }
+
+    // The number of `name`, given the next one if it's new.
+    fn insert(&mut self, name: &'a str, next: u32) -> u32 {
+        match pack(name) {
+            Some(key) => *self.packed.entry(key).or_insert(next),
+            None => *self.long.entry(name).or_insert(next),
+        }
+    }
+}
+
+struct Compiler<'a> {
+    code: Vec<Code>,
+    names: Vec<&'a str>,
+    index: Index<'a>,
+    consts: Vec<i32>,
+    labels: Vec<Option<usize>>,
+    // code offsets still holding a label id
+    fixups: Vec<usize>,
+}
+
+// Where an update's condition sends it when false: past the update itself.
+// Resolved as soon as the update is emitted, so it never needs a label.
+const SKIP: u32 = u32::MAX;
+
+impl<'a> Compiler<'a> {
+    fn reg(&mut self, name: &'a str) -> u32 {
+        let reg = self.index.insert(name, self.names.len() as u32);
+        if reg as usize == self.names.len() {
+            self.names.push(name);
+        }
+        reg
+    }
+
+    fn src(&mut self, operand: Operand<'a>) -> Src {
+        match operand {
+            Operand::Literal(val) => {
+                self.consts.push(val);
+                Src::Imm(self.consts.len() as u32 - 1)
+            }
+            Operand::Register(name) => Src::Reg(self.reg(name)),
+        }
+    }
+
+    fn new_label(&mut self) -> u32 {
+        self.labels.push(None);
+        self.labels.len() as u32 - 1
+    }
+
+    fn place(&mut self, label: u32) {
+        self.labels[label as usize] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, mut code: Code) {
+        if let Code::Branch { target, .. } | Code::Jump { target } = &mut code {
+            match self.labels.get(*target as usize) {
+                Some(&Some(offset)) => *target = offset as u32,
+                Some(None) => self.fixups.push(self.code.len()),
+                None => {}
+            }
+        }
+        self.code.push(code);
+    }
+
+    // Emit code that jumps to `target` when `cond` evaluates to `when`, and
+    // falls through otherwise, keeping the interpreter's short-circuiting.
+    fn branch(&mut self, cond: &Condition<'a>, when: bool, target: u32) {
+        match cond {
+            Condition::Compare(cmp) => {
+                let lhs = self.reg(cmp.reg);
+                let rhs = self.src(cmp.operand);
+                let cmp = if when { cmp.op } else { cmp.op.negate() };
+                self.emit(Code::Branch {
+                    lhs,
+                    cmp,
+                    rhs,
+                    target,
+                });
+            }
+            Condition::Not(cond) => self.branch(cond, !when, target),
+            Condition::And(lhs, rhs) if !when => {
+                self.branch(lhs, false, target);
+                self.branch(rhs, false, target);
+            }
+            Condition::Or(lhs, rhs) if when => {
+                self.branch(lhs, true, target);
+                self.branch(rhs, true, target);
+            }
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                let settled = matches!(cond, Condition::Or(..));
+                let skip = self.new_label();
+                self.branch(lhs, settled, skip);
+                self.branch(rhs, when, target);
+                self.place(skip);
+            }
+        }
+    }
+
+    fn jump(&mut self, cond: &Option<Condition<'a>>, target: u32) {
+        match cond {
+            Some(cond) => self.branch(cond, true, target),
+            None => self.emit(Code::Jump { target }),
+        }
+    }
+}
+
+impl<'a> Bytecode<'a> {
+    pub fn new(input: &'a str) -> Result<Self> {
+        Ok(Self::compile(&Cpu::new(input)?))
+    }
+
+    pub fn compile(cpu: &Cpu<'a>) -> Self {
+        let len = cpu.inst.len();
+        let mut c = Compiler {
+            code: Vec::with_capacity(2 * len),
+            names: Vec::new(),
+            index: Index::default(),
+            consts: Vec::with_capacity(2 * len),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        };
+        let mut starts = Vec::with_capacity(len);
+
+        let labels: HashMap<&str, u32> = cpu
+            .labels
+            .keys()
+            .map(|&label| (label, c.new_label()))
+            .collect();
+        let end = c.new_label();
+
+        for stmt in cpu.instructions() {
+            starts.push(c.code.len() as u32);
+            match stmt {
+                Statement::Update(inst) => {
+                    let start = c.code.len();
+                    c.branch(&inst.cond, false, SKIP);
+                    let src = c.src(inst.operand);
+                    let dst = c.reg(inst.reg);
+                    c.emit(Code::Apply {
+                        dst,
+                        op: inst.op,
+                        src,
+                    });
+
+                    let next = c.code.len() as u32;
+                    for code in &mut c.code[start..] {
+                        if let Code::Branch { target, .. } = code {
+                            if *target == SKIP {
+                                *target = next;
+                            }
+                        }
+                    }
+                }
+                Statement::Label(label) => c.place(labels[label]),
+                Statement::Jump { label, cond } => c.jump(cond, labels[label]),
+                Statement::Halt { cond } => c.jump(cond, end),
+            }
+        }
+        c.place(end);
+
+        for &at in &c.fixups {
+            if let Code::Branch { target, .. } | Code::Jump { target } = &mut c.code[at] {
+                *target = c.labels[*target as usize].unwrap() as u32;
+            }
+        }
+
+        Self {
+            code: c.code,
+            starts,
+            names: c.names,
+            index: c.index,
+            consts: c.consts,
+        }
+    }
+
+    // The statement the code at `offset` came from.
+    fn statement(&self, offset: usize) -> usize {
+        self.starts
+            .partition_point(|&start| start as usize <= offset)
+            - 1
+    }
+
+    /// Runs the program from a clean register file. The step limit counts
+    /// bytecode operations rather than source lines.
+    pub fn run<V: Value>(&self, step_limit: usize) -> Result<Registers<'a, V>, ExecError> {
+        let consts: Vec<V> = self.consts.iter().map(|&val| V::from_i32(val)).collect();
+        let mut values = vec![V::default(); self.names.len()];
+        let mut touched = vec![false; self.names.len()];
+        let mut max: Option<V> = None;
+        let mut steps = 0;
+        let mut pc = 0;
+
+        macro_rules! read {
+            ($src:expr) => {
+                match $src {
+                    Src::Reg(r) => {
+                        touched[r as usize] = true;
+                        &values[r as usize]
+                    }
+                    Src::Imm(c) => &consts[c as usize],
+                }
+            };
+        }
+
+        while let Some(&code) = self.code.get(pc) {
+            if steps == step_limit {
+                return Err(ExecError::StepLimit { limit: step_limit });
+            }
+            steps += 1;
+            pc += 1;
+
+            match code {
+                Code::Apply { dst, op, src } => {
+                    let dst = dst as usize;
+                    touched[dst] = true;
+                    let val = op
+                        .apply(&values[dst], read!(src))
+                        .map_err(|fault| fault.at(self.statement(pc - 1)))?;
+                    if max.as_ref().is_none_or(|max| val > *max) {
+                        max = Some(val.clone());
+                    }
+                    values[dst] = val;
+                }
+                Code::Branch {
+                    lhs,
+                    cmp,
+                    rhs,
+                    target,
+                } => {
+                    let lhs = read!(Src::Reg(lhs));
+                    let rhs = read!(rhs);
+                    if cmp.test(lhs.cmp(rhs)) {
+                        pc = target as usize;
+                    }
+                }
+                Code::Jump { target } => pc = target as usize,
+            }
+        }
+
+        Ok(Registers {
+            names: self.names.clone(),
+            index: self.index.clone(),
+            values,
+            touched,
+            max,
+        })
+    }
+}
+
+impl<V: Value> Registers<'_, V> {
+    pub fn get(&self, reg: &str) -> V {
+        self.index
+            .get(reg)
+            .map(|r| self.values[r as usize].clone())
+            .unwrap_or_default()
+    }
+
+    /// Registers the run actually named, with their final values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> + '_ {
+        self.names
+            .iter()
+            .zip(self.values.iter())
+            .zip(self.touched.iter())
+            .filter(|(_, &touched)| touched)
+            .map(|((&name, val), _)| (name, val))
+    }
+
+    /// The highest value any register held during the run.
+    pub fn max(&self) -> Option<V> {
+        self.max.clone()
+    }
+
+    pub fn largest_register(&self) -> Option<V> {
+        self.iter().map(|(_, val)| val).max().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day8::DEFAULT_STEP_LIMIT;
+    use crate::testutil::lcg;
+
+    // Run a program both ways and check the machines agree.
+    fn check(input: &str) {
+        let mut cpu = Cpu::new(input).unwrap();
+        let cpu_result = cpu.exec();
+        let bytecode = Bytecode::compile(&cpu).run(DEFAULT_STEP_LIMIT);
+
+        match (cpu_result, bytecode) {
+            (Ok(()), Ok(regs)) => {
+                let mut expected: Vec<_> = cpu.registers().iter().map(|(&k, &v)| (k, v)).collect();
+                let mut actual: Vec<_> = regs.iter().map(|(k, &v)| (k, v)).collect();
+                expected.sort();
+                actual.sort();
+                assert_eq!(actual, expected);
+                for (&name, &val) in cpu.registers() {
+                    assert_eq!(regs.get(name), val);
+                }
+                assert_eq!(regs.get("unnamed"), 0);
+                assert_eq!(regs.max(), cpu.max());
+                assert_eq!(regs.largest_register(), cpu.largest_register());
+            }
+            (Err(expected), Err(actual)) => assert_eq!(actual, expected),
+            (expected, actual) => panic!("{:?} vs {:?}", expected, actual.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_example() {
+        check(
+            "b inc 5 if a > 1\n\
+             a inc 1 if b < 5\n\
+             c dec -10 if a >= 1\n\
+             c inc -20 if c == 10",
+        );
+    }
+
+    #[test]
+    fn test_compound_conditions() {
+        check(
+            "a inc 5 if b > 1 and c == 0\n\
+             a inc 1 if b == 0 or c == 0\n\
+             a inc 2 if not (d > 0 or e > 0)\n\
+             f dec 3 if not (a > 1 and g < 0) or h == 0\n\
+             i dec 1 if (a > 100 or b > 100) and j == 0",
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        check(
+            "x set 10 if x == 0\n\
+             top:\n\
+             sum inc x if x > 0\n\
+             x dec 1 if x > 0\n\
+             halt if sum > 50\n\
+             jmp top if x > 0\n\
+             sum set -1 if x == 0",
+        );
+        check("x dec 1 if x == 0\nhalt\ny inc 1 if x < 0");
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        check("a inc 1 if a == 0\nb div c if a > 0\na inc 1 if a > 0");
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let bytecode = Bytecode::new("top:\na inc 1 if a >= 0\njmp top").unwrap();
+        assert_eq!(
+            bytecode.run::<i32>(1000).map(|_| ()),
+            Err(ExecError::StepLimit { limit: 1000 })
+        );
+    }
+
+    #[test]
+    fn test_generated() {
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let ops = ["inc", "dec", "mul", "div", "mod", "set", "min", "max"];
+        let cmps = [">", ">=", "<", "<=", "==", "!="];
+
+        // the same program on every run
+        let mut next = lcg(12345);
+
+        let mut program = String::new();
+        for _ in 0..2000 {
+            let op = ops[next(ops.len())];
+            // only let registers feed ops that can't compound, so the
+            // values stay well inside i32
+            let operand = match op {
+                "div" | "mod" => (next(9) + 1).to_string(),
+                "mul" => (next(3) as i32 - 1).to_string(),
+                "set" | "min" | "max" if next(2) == 0 => names[next(names.len())].to_string(),
+                _ => (next(200) as i32 - 100).to_string(),
+            };
+            program.push_str(&format!(
+                "{} {} {} if {} {} {} {} {} {} {}\n",
+                names[next(names.len())],
+                op,
+                operand,
+                names[next(names.len())],
+                cmps[next(cmps.len())],
+                next(20) as i32 - 10,
+                ["and", "or"][next(2)],
+                names[next(names.len())],
+                cmps[next(cmps.len())],
+                names[next(names.len())],
+            ));
+        }
+
+        check(&program);
+    }
+}
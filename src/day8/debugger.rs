@@ -0,0 +1,537 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use nom::combinator::all_consuming;
+
+use super::{Condition, Cpu, Instruction, Operand, DEFAULT_STEP_LIMIT};
+
+const HELP: &str = "\
+load FILE          load a program and reset
+step [N], s [N]    run the next N instructions (default 1), stopping early
+                   at a breakpoint
+continue, c        run until a breakpoint, halt or error
+limit N            give up after N more instructions when running
+break LINE         stop before running LINE
+break when COND    stop when COND starts to hold, e.g. `break when a > 100`
+breaks             list breakpoints
+delete ID          remove a breakpoint
+print [REG], p     show one register, or all of them
+set REG VALUE      change a register
+max                show the highest value held so far
+list, l            show the program
+reset              clear registers and go back to the first line
+quit, q            leave
+Anything else is run as an instruction, e.g. `a inc 5 if b > 0`.";
+
+enum Breakpoint {
+    Line(usize),
+    // the condition's text, starting `if `, and whether it held after the
+    // last step
+    When(String, Cell<bool>),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::When(text, _) => write!(f, "when {}", &text[3..]),
+        }
+    }
+}
+
+// A breakpoint ready to check against the cpu, its condition parsed.
+enum Stop<'a> {
+    Line(usize),
+    When(Condition<'a>, &'a Cell<bool>),
+}
+
+// Everything about the machine that lasts from one command to the next.
+// The cpu borrows its register names, so this keeps its own copy of them.
+struct State {
+    regs: HashMap<String, i32>,
+    pc: usize,
+    steps: usize,
+    step_limit: usize,
+    max: Option<i32>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            regs: HashMap::new(),
+            pc: 0,
+            steps: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+            max: None,
+        }
+    }
+}
+
+impl State {
+    fn save(cpu: &Cpu) -> Self {
+        Self {
+            regs: cpu
+                .reg
+                .iter()
+                .map(|(&name, &val)| (name.to_string(), val))
+                .collect(),
+            pc: cpu.pc,
+            steps: cpu.steps,
+            step_limit: cpu.step_limit,
+            max: cpu.max,
+        }
+    }
+
+    // A cpu running `program`, which was checked when it was loaded,
+    // picking up where this left off.
+    fn restore<'a>(&'a self, program: &'a str) -> Cpu<'a> {
+        let mut cpu = match program {
+            "" => Cpu::with_instructions(Vec::new()),
+            program => Cpu::new(program),
+        }
+        .expect("the program was checked when loaded");
+
+        cpu.reg = self
+            .regs
+            .iter()
+            .map(|(name, &val)| (name.as_str(), val))
+            .collect();
+        cpu.pc = self.pc;
+        cpu.steps = self.steps;
+        cpu.step_limit = self.step_limit;
+        cpu.max = self.max;
+        cpu
+    }
+}
+
+/// A line oriented debugger around a `Cpu`, reading commands from any
+/// reader so sessions can be scripted.
+///
+/// A cpu borrows its program text and the names of its registers, so
+/// rather than keep one alive alongside what it borrows, the debugger keeps
+/// the text and the machine's state, and each command runs on a cpu
+/// rebuilt from them. That parses the program again every time, which for
+/// typing commands is no time at all.
+pub struct Debugger {
+    program: String,
+    state: State,
+    // deleted breakpoints leave a hole so the others keep their ids
+    breakpoints: Vec<Option<Breakpoint>>,
+}
+
+// One command's worth of debugging, on a cpu that borrows from the
+// debugger and from the command line itself.
+struct Session<'a, 'b> {
+    cpu: Cpu<'a>,
+    breakpoints: &'b mut Vec<Option<Breakpoint>>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            program: String::new(),
+            state: State::default(),
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            if !self.command(&line?, &mut output)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Carries out one command, returning `false` when asked to quit.
+    pub fn command(&mut self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+        let line = line.trim();
+
+        // instructions come first, since registers are free to be called
+        // `b` or `p`
+        if let Some(path) = line.strip_prefix("load ") {
+            if all_consuming(Instruction::parse)(line).is_err() {
+                self.load(path.trim(), out)?;
+                return Ok(true);
+            }
+        }
+
+        let mut session = Session {
+            cpu: self.state.restore(&self.program),
+            breakpoints: &mut self.breakpoints,
+        };
+        let more = session.command(line, out);
+        let state = State::save(&session.cpu);
+        drop(session);
+        self.state = state;
+
+        more
+    }
+
+    fn load(&mut self, path: &str, out: &mut impl Write) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text.trim_end().to_string(),
+            Err(e) => return writeln!(out, "error: cannot read {}: {}", path, e),
+        };
+
+        let state = match Cpu::new(&text) {
+            Ok(cpu) => {
+                writeln!(out, "loaded {} lines", cpu.instructions().len())?;
+                location(&cpu, out)?;
+                State::save(&cpu)
+            }
+            Err(e) => return writeln!(out, "error: {}", e),
+        };
+        self.program = text;
+        self.state = state;
+
+        Ok(())
+    }
+}
+
+fn location(cpu: &Cpu, out: &mut impl Write) -> io::Result<()> {
+    match cpu.instructions().get(cpu.pc()) {
+        Some(stmt) => writeln!(out, "=> {}: {}", cpu.pc() + 1, stmt),
+        None => writeln!(out, "halted"),
+    }
+}
+
+fn holds(cpu: &Cpu, cond: &Condition) -> bool {
+    cond.test(&mut |operand| match operand {
+        Operand::Literal(val) => val,
+        Operand::Register(reg) => cpu.get(reg),
+    })
+}
+
+impl<'a> Session<'a, '_> {
+    fn command(&mut self, line: &'a str, out: &mut impl Write) -> io::Result<bool> {
+        if let Ok((_, inst)) = all_consuming(Instruction::parse)(line) {
+            self.eval(&inst, out)?;
+            return Ok(true);
+        }
+
+        let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+
+        match cmd {
+            "" => {}
+            "quit" | "q" => return Ok(false),
+            "help" | "h" => writeln!(out, "{}", HELP)?,
+            "load" => writeln!(out, "error: expected `load FILE`")?,
+            "step" | "s" => match arg {
+                "" => self.run_for(Some(1), out)?,
+                n => match n.parse() {
+                    Ok(n) => self.run_for(Some(n), out)?,
+                    Err(_) => writeln!(out, "error: not a count: {}", n)?,
+                },
+            },
+            "continue" | "c" => self.run_for(None, out)?,
+            "limit" => match arg.parse::<usize>() {
+                Ok(limit) => {
+                    self.cpu
+                        .set_step_limit(self.cpu.steps().saturating_add(limit));
+                    writeln!(out, "limit = {}", limit)?;
+                }
+                Err(_) => writeln!(out, "error: not a count: {}", arg)?,
+            },
+            "break" | "b" => self.add_breakpoint(arg, out)?,
+            "breaks" => {
+                for (id, bp) in self.breakpoints.iter().enumerate() {
+                    if let Some(bp) = bp {
+                        writeln!(out, "breakpoint {}: {}", id + 1, bp)?;
+                    }
+                }
+            }
+            "delete" => match arg.parse::<usize>() {
+                Ok(id)
+                    if self
+                        .breakpoints
+                        .get(id.wrapping_sub(1))
+                        .is_some_and(Option::is_some) =>
+                {
+                    self.breakpoints[id - 1] = None;
+                }
+                _ => writeln!(out, "error: no breakpoint {}", arg)?,
+            },
+            "print" | "p" if arg.is_empty() => {
+                let mut regs: Vec<_> = self.cpu.registers().iter().collect();
+                regs.sort();
+                for (reg, val) in regs {
+                    writeln!(out, "{} = {}", reg, val)?;
+                }
+            }
+            "print" | "p" => writeln!(out, "{} = {}", arg, self.cpu.get(arg))?,
+            "set" => self.set(arg, out)?,
+            "max" => match self.cpu.max() {
+                Some(max) => writeln!(out, "max = {}", max)?,
+                None => writeln!(out, "max = none")?,
+            },
+            "list" | "l" => {
+                for (pc, stmt) in self.cpu.instructions().iter().enumerate() {
+                    let marker = if pc == self.cpu.pc() { "=>" } else { "  " };
+                    writeln!(out, "{} {:>3}: {}", marker, pc + 1, stmt)?;
+                }
+            }
+            "reset" => {
+                self.cpu.reset();
+                location(&self.cpu, out)?;
+            }
+            _ => writeln!(out, "error: unknown command: {}", line)?,
+        }
+
+        Ok(true)
+    }
+
+    // Runs up to `count` instructions, or with no count until the program
+    // halts, stopping early at a breakpoint either way.
+    fn run_for(&mut self, count: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+        let stops: Vec<_> = self
+            .breakpoints
+            .iter()
+            .map(|bp| match bp.as_ref()? {
+                Breakpoint::Line(line) => Some(Stop::Line(*line)),
+                Breakpoint::When(text, held) => {
+                    let (_, cond) = Condition::parse(text).ok()?;
+                    Some(Stop::When(cond, held))
+                }
+            })
+            .collect();
+
+        for _ in 0..count.unwrap_or(usize::MAX) {
+            match self.cpu.step() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => return writeln!(out, "error: {}", e),
+            }
+
+            if let Some(id) = hit(&self.cpu, &stops) {
+                let bp = self.breakpoints[id].as_ref().unwrap();
+                writeln!(out, "breakpoint {}: {}", id + 1, bp)?;
+                break;
+            }
+        }
+
+        location(&self.cpu, out)
+    }
+
+    fn add_breakpoint(&mut self, arg: &str, out: &mut impl Write) -> io::Result<()> {
+        let bp = if let Some(expr) = arg.strip_prefix("when ") {
+            let text = format!("if {}", expr.trim());
+            let held = match all_consuming(Condition::parse)(&text) {
+                Ok((_, cond)) => holds(&self.cpu, &cond),
+                Err(_) => return writeln!(out, "error: bad condition: {}", expr),
+            };
+            Breakpoint::When(text, Cell::new(held))
+        } else {
+            match arg.parse() {
+                Ok(line) => Breakpoint::Line(line),
+                Err(_) => return writeln!(out, "error: expected a line or `when COND`"),
+            }
+        };
+
+        writeln!(out, "breakpoint {}: {}", self.breakpoints.len() + 1, bp)?;
+        self.breakpoints.push(Some(bp));
+        Ok(())
+    }
+
+    fn set(&mut self, arg: &'a str, out: &mut impl Write) -> io::Result<()> {
+        let parsed = arg
+            .split_once(' ')
+            .filter(|(reg, _)| !reg.is_empty() && reg.chars().all(|c| c.is_ascii_alphabetic()))
+            .and_then(|(reg, val)| Some((reg, val.trim().parse().ok()?)));
+
+        match parsed {
+            Some((reg, val)) => {
+                self.cpu.set(reg, val);
+                writeln!(out, "{} = {}", reg, val)
+            }
+            None => writeln!(out, "error: expected `set REG VALUE`"),
+        }
+    }
+
+    fn eval(&mut self, inst: &Instruction<'a>, out: &mut impl Write) -> io::Result<()> {
+        match self.cpu.eval(inst) {
+            Ok(true) => writeln!(out, "{} = {}", inst.reg, self.cpu.get(inst.reg)),
+            Ok(false) => writeln!(out, "condition does not hold"),
+            Err(fault) => writeln!(out, "error: {}", fault),
+        }
+    }
+}
+
+// Finds the first breakpoint that fires now. Conditions only fire on the
+// step where they go from false to true, so `continue` can get past them.
+fn hit(cpu: &Cpu, stops: &[Option<Stop>]) -> Option<usize> {
+    let mut hit = None;
+    for (id, stop) in stops.iter().enumerate() {
+        let fired = match stop {
+            Some(Stop::Line(line)) => cpu.pc() + 1 == *line,
+            Some(Stop::When(cond, held)) => {
+                let now = holds(cpu, cond);
+                !held.replace(now) && now
+            }
+            None => false,
+        };
+
+        if fired && hit.is_none() {
+            hit = Some(id);
+        }
+    }
+
+    hit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(script: &str) -> String {
+        let mut out = Vec::new();
+        Debugger::new().run(script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn program(name: &str, text: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("day8-debugger-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_step_and_inspect() {
+        let path = program(
+            "example",
+            "b inc 5 if a > 1\n\
+             a inc 1 if b < 5\n\
+             c dec -10 if a >= 1\n\
+             c inc -20 if c == 10\n",
+        );
+
+        assert_eq!(
+            session(&format!(
+                "load {}\ns 2\np\nset b 7\np b\nstep\nmax\ns 5\nquit\np",
+                path
+            )),
+            format!(
+                "loaded 4 lines\n\
+                 => 1: b inc 5 if a > 1\n\
+                 => 3: c dec -10 if a >= 1\n\
+                 a = 1\n\
+                 b = 0\n\
+                 b = 7\n\
+                 b = 7\n\
+                 => 4: c inc -20 if c == 10\n\
+                 max = 10\n\
+                 halted\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let path = program(
+            "loop",
+            "top:\n\
+             a inc 7 if a >= 0\n\
+             n inc 1 if a > 0\n\
+             jmp top if n < 100\n",
+        );
+
+        assert_eq!(
+            session(&format!(
+                "load {}\nbreak 3\nbreak when a > 20\nbreaks\nc\nc\np\ndelete 1\nc\np n\ndelete 1\nc\nc",
+                path
+            )),
+            "loaded 4 lines\n\
+             => 1: top:\n\
+             breakpoint 1: line 3\n\
+             breakpoint 2: when a > 20\n\
+             breakpoint 1: line 3\n\
+             breakpoint 2: when a > 20\n\
+             breakpoint 1: line 3\n\
+             => 3: n inc 1 if a > 0\n\
+             breakpoint 1: line 3\n\
+             => 3: n inc 1 if a > 0\n\
+             a = 14\n\
+             n = 1\n\
+             breakpoint 2: when a > 20\n\
+             => 3: n inc 1 if a > 0\n\
+             n = 2\n\
+             error: no breakpoint 1\n\
+             halted\n\
+             halted\n"
+        );
+    }
+
+    #[test]
+    fn test_step_stops_at_breakpoint() {
+        let path = program("step", "top:\na inc 1 if a >= 0\njmp top if a < 10\n");
+
+        assert_eq!(
+            session(&format!(
+                "load {}\nbreak 2\ns 100\ns 1\ns 2\np a\nset a 9\ns 100",
+                path
+            )),
+            "loaded 3 lines\n\
+             => 1: top:\n\
+             breakpoint 1: line 2\n\
+             breakpoint 1: line 2\n\
+             => 2: a inc 1 if a >= 0\n\
+             => 3: jmp top if a < 10\n\
+             breakpoint 1: line 2\n\
+             => 2: a inc 1 if a >= 0\n\
+             a = 1\n\
+             a = 9\n\
+             halted\n"
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(
+            session("a inc 5 if b == 0\nb dec 2 if a < 5\nb mul a if a == 5\np\nbogus\nb div c if a > 0"),
+            "a = 5\n\
+             condition does not hold\n\
+             b = 0\n\
+             a = 5\n\
+             b = 0\n\
+             error: unknown command: bogus\n\
+             error: division by zero\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let path = program("spin", "top:\njmp top\n");
+        let out = session(&format!(
+            "load /nonexistent/day8\nload {}\nlimit 1000\nc\nbreak when >\nset 1 a",
+            path
+        ));
+        let lines: Vec<_> = out.lines().collect();
+
+        assert!(lines[0].starts_with("error: cannot read /nonexistent/day8"));
+        assert_eq!(lines[3], "limit = 1000");
+        assert_eq!(lines[4], "error: program did not halt within 1000 steps");
+        assert_eq!(lines[5], "error: bad condition: >");
+        assert_eq!(lines[6], "error: expected `set REG VALUE`");
+        let bad = program(
+            "bad",
+            "a inc 1 if b > 0\na inc if b > 0\nb inc 1 if a > 0\n",
+        );
+        assert_eq!(
+            session(&format!("load {}\nlist", bad)),
+            "error: Unable to parse line 2: a inc if b > 0\n"
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::{HashMap, HashSet};
+
+use super::value::Fault;
+use super::{Cmp, Condition, Op, Operand, Statement};
+
+const MIN: i64 = i32::MIN as i64;
+const MAX: i64 = i32::MAX as i64;
+
+/// An inclusive range of values a register might hold. Results are clamped
+/// to `i32`, since anything outside would have stopped the program with an
+/// overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub const TOP: Interval = Interval { lo: MIN, hi: MAX };
+
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Self {
+            lo: lo.clamp(MIN, MAX),
+            hi: hi.clamp(MIN, MAX),
+        }
+    }
+
+    pub fn point(val: i32) -> Self {
+        Self::new(val.into(), val.into())
+    }
+
+    pub fn contains(self, val: i64) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    pub fn join(self, other: Self) -> Self {
+        Self::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    // The unclamped bounds of `f` over the box. Within i32 nothing here
+    // can overflow an i64.
+    fn corners(f: impl Fn(i64, i64) -> i64, lhs: Self, rhs: Self) -> (i64, i64) {
+        let vals = [
+            f(lhs.lo, rhs.lo),
+            f(lhs.lo, rhs.hi),
+            f(lhs.hi, rhs.lo),
+            f(lhs.hi, rhs.hi),
+        ];
+        (*vals.iter().min().unwrap(), *vals.iter().max().unwrap())
+    }
+
+    // The exact bounds of `op`, before clamping, or `None` if every pair
+    // divides by zero.
+    fn raw(op: Op, lhs: Self, rhs: Self) -> Option<(i64, i64)> {
+        Some(match op {
+            Op::Inc => (lhs.lo + rhs.lo, lhs.hi + rhs.hi),
+            Op::Dec => (lhs.lo - rhs.hi, lhs.hi - rhs.lo),
+            Op::Mul => Self::corners(|a, b| a * b, lhs, rhs),
+            Op::Div => {
+                // truncating division is monotonic either side of zero, so
+                // the corners of each half bound it
+                let div = |rhs| Self::corners(|a, b| a / b, lhs, rhs);
+                let neg = (rhs.lo <= -1).then(|| div(Self::new(rhs.lo, rhs.hi.min(-1))));
+                let pos = (rhs.hi >= 1).then(|| div(Self::new(rhs.lo.max(1), rhs.hi)));
+                match (neg, pos) {
+                    (Some(neg), Some(pos)) => (neg.0.min(pos.0), neg.1.max(pos.1)),
+                    (neg, pos) => neg.or(pos)?,
+                }
+            }
+            Op::Mod => {
+                if rhs == Self::point(0) {
+                    return None;
+                }
+                // the result is smaller than the divisor and takes the sign
+                // of the dividend
+                let bound = rhs.lo.abs().max(rhs.hi.abs()) - 1;
+                (lhs.lo.max(-bound).min(0), lhs.hi.min(bound).max(0))
+            }
+            Op::Set => (rhs.lo, rhs.hi),
+            Op::Min => (lhs.lo.min(rhs.lo), lhs.hi.min(rhs.hi)),
+            Op::Max => (lhs.lo.max(rhs.lo), lhs.hi.max(rhs.hi)),
+        })
+    }
+
+    /// The values `op` can produce from any pair drawn from the two ranges,
+    /// or `None` if every pair divides by zero.
+    pub fn apply(op: Op, lhs: Self, rhs: Self) -> Option<Self> {
+        Self::raw(op, lhs, rhs).map(|(lo, hi)| Self::new(lo, hi))
+    }
+
+    /// What might go wrong applying `op` to values from the two ranges.
+    pub fn faults(op: Op, lhs: Self, rhs: Self) -> Vec<Fault> {
+        let mut faults = Vec::new();
+        if matches!(op, Op::Div | Op::Mod) && rhs.contains(0) {
+            faults.push(Fault::DivideByZero);
+        }
+
+        // i32::MIN % -1 overflows even though the answer would fit
+        let overflow = match Self::raw(op, lhs, rhs) {
+            Some((lo, hi)) => lo < MIN || hi > MAX,
+            None => false,
+        };
+        if overflow || (op == Op::Mod && lhs.contains(MIN) && rhs.contains(-1)) {
+            faults.push(Fault::Overflow);
+        }
+
+        faults
+    }
+
+    /// Whether `lhs cmp rhs` can hold, and whether it can fail, for values
+    /// drawn from the two ranges.
+    pub fn compare(lhs: Self, cmp: Cmp, rhs: Self) -> (bool, bool) {
+        let lt = lhs.lo < rhs.hi;
+        let gt = lhs.hi > rhs.lo;
+        let eq = lhs.lo <= rhs.hi && rhs.lo <= lhs.hi;
+        let single = lhs.lo == lhs.hi && rhs.lo == rhs.hi;
+
+        match cmp {
+            Cmp::Lt => (lt, gt || eq),
+            Cmp::Le => (lt || eq, gt),
+            Cmp::Gt => (gt, lt || eq),
+            Cmp::Ge => (gt || eq, lt),
+            Cmp::Eq => (eq, !(eq && single)),
+            Cmp::Ne => (!(eq && single), eq),
+        }
+    }
+}
+
+/// Register ranges at one point in a program. Registers that haven't been
+/// written are 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Env<'a>(HashMap<&'a str, Interval>);
+
+impl<'a> Env<'a> {
+    pub fn get(&self, reg: &str) -> Interval {
+        self.0.get(reg).copied().unwrap_or(Interval::point(0))
+    }
+
+    pub fn set(&mut self, reg: &'a str, range: Interval) {
+        self.0.insert(reg, range);
+    }
+
+    /// The ranges covering both states.
+    pub fn join(&self, other: &Self) -> Self {
+        let mut joined = self.clone();
+        for &reg in self.0.keys().chain(other.0.keys()) {
+            joined.set(reg, self.get(reg).join(other.get(reg)));
+        }
+        joined
+    }
+
+    pub fn operand(&self, operand: Operand) -> Interval {
+        match operand {
+            Operand::Literal(val) => Interval::point(val),
+            Operand::Register(reg) => self.get(reg),
+        }
+    }
+
+    /// Whether `cond` can hold, and whether it can fail, in this state.
+    pub fn test(&self, cond: &Condition) -> (bool, bool) {
+        match cond {
+            Condition::Compare(cmp) => {
+                Interval::compare(self.get(cmp.reg), cmp.op, self.operand(cmp.operand))
+            }
+            Condition::Not(cond) => {
+                let (holds, fails) = self.test(cond);
+                (fails, holds)
+            }
+            Condition::And(lhs, rhs) => {
+                let (lhs_holds, lhs_fails) = self.test(lhs);
+                let (rhs_holds, rhs_fails) = self.test(rhs);
+                (lhs_holds && rhs_holds, lhs_fails || rhs_fails)
+            }
+            Condition::Or(lhs, rhs) => {
+                let (lhs_holds, lhs_fails) = self.test(lhs);
+                let (rhs_holds, rhs_fails) = self.test(rhs);
+                (lhs_holds || rhs_holds, lhs_fails && rhs_fails)
+            }
+        }
+    }
+}
+
+// The first comparison of a condition is always evaluated, so the
+// registers it names are sure to exist afterwards.
+fn first_compare<'a>(cond: &Condition<'a>) -> Vec<&'a str> {
+    match cond {
+        Condition::Compare(cmp) => match cmp.operand {
+            Operand::Register(reg) => vec![cmp.reg, reg],
+            Operand::Literal(_) => vec![cmp.reg],
+        },
+        Condition::Not(cond) | Condition::And(cond, _) | Condition::Or(cond, _) => {
+            first_compare(cond)
+        }
+    }
+}
+
+fn mentions<'a>(cond: &Condition<'a>, regs: &mut HashSet<&'a str>) {
+    match cond {
+        Condition::Compare(cmp) => {
+            regs.insert(cmp.reg);
+            if let Operand::Register(reg) = cmp.operand {
+                regs.insert(reg);
+            }
+        }
+        Condition::Not(cond) => mentions(cond, regs),
+        Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+            mentions(lhs, regs);
+            mentions(rhs, regs);
+        }
+    }
+}
+
+// Narrows down the range of a maximum taken over values as they are seen.
+// Values that are certain to turn up raise the lower bound.
+#[derive(Default)]
+struct MaxBound {
+    floor: Option<i64>,
+    range: Option<Interval>,
+}
+
+impl MaxBound {
+    fn see(&mut self, range: Interval, certain: bool) {
+        self.range = Some(self.range.map_or(range, |seen| seen.join(range)));
+        if certain {
+            self.floor = Some(self.floor.map_or(range.lo, |floor| floor.max(range.lo)));
+        }
+    }
+
+    fn bound(&self) -> Option<Interval> {
+        let range = self.range?;
+        Some(Interval::new(self.floor.unwrap_or(range.lo), range.hi))
+    }
+}
+
+/// What abstract interpretation of a program found: the ranges registers
+/// can hold at each line, bounds on the puzzle's two answers, and the
+/// lines that might fail. Values are taken to be `i32`, as in the default
+/// `Cpu`.
+#[derive(Debug, Clone)]
+pub struct Bounds<'a> {
+    states: Vec<Option<Env<'a>>>,
+    last: Env<'a>,
+    largest: Option<Interval>,
+    max: Option<Interval>,
+    faults: Vec<(usize, Fault)>,
+}
+
+impl<'a> Bounds<'a> {
+    /// Works through `program` once with ranges in place of values, taking
+    /// both sides of every condition that could go either way. The
+    /// language is meant to be straight-line; once a program has a jump,
+    /// any line could follow any other, so every register it writes is
+    /// treated as unknown throughout.
+    pub fn new(program: &[Statement<'a>]) -> Self {
+        Self::with_initial(program, Env::default())
+    }
+
+    /// Like `new`, but starting from `env` rather than every register at 0.
+    /// From all zeroes a straight-line program only ever has one path, so
+    /// the ranges come out exact; this is for asking about inputs, such as
+    /// registers preset with `Cpu::set`.
+    pub fn with_initial(program: &[Statement<'a>], mut env: Env<'a>) -> Self {
+        let looping = program
+            .iter()
+            .any(|stmt| matches!(stmt, Statement::Jump { .. }));
+
+        // preset registers exist from the start, and count toward the
+        // running max just as `Cpu::set` does
+        let mut existing: HashSet<_> = env.0.keys().copied().collect();
+        let mut possible = existing.clone();
+        let mut max = MaxBound::default();
+        for range in env.0.values() {
+            max.see(*range, true);
+        }
+
+        if looping {
+            for stmt in program.iter() {
+                if let Statement::Update(inst) = stmt {
+                    env.set(inst.reg, Interval::TOP);
+                }
+            }
+        }
+
+        let mut states = vec![Some(env.clone())];
+        let mut halted: Option<Env<'a>> = None;
+        let mut reachable = true;
+        // whether every line so far has run, so this one will too
+        let mut certain = !looping;
+        let mut faults = Vec::new();
+
+        for (pc, stmt) in program.iter().enumerate() {
+            if !reachable {
+                states.push(None);
+                continue;
+            }
+
+            let cond = match stmt {
+                Statement::Update(inst) => Some(&inst.cond),
+                Statement::Jump { cond, .. } | Statement::Halt { cond } => cond.as_ref(),
+                Statement::Label(_) => None,
+            };
+            let (holds, fails) = cond.map_or((true, false), |cond| env.test(cond));
+            if let Some(cond) = cond {
+                mentions(cond, &mut possible);
+                if certain {
+                    existing.extend(first_compare(cond));
+                }
+            }
+
+            match stmt {
+                Statement::Update(inst) if holds => {
+                    let old = env.get(inst.reg);
+                    let rhs = env.operand(inst.operand);
+                    for fault in Interval::faults(inst.op, old, rhs) {
+                        faults.push((pc, fault));
+                    }
+
+                    possible.insert(inst.reg);
+                    if let Operand::Register(reg) = inst.operand {
+                        possible.insert(reg);
+                    }
+                    if certain && !fails {
+                        existing.insert(inst.reg);
+                        existing.extend(match inst.operand {
+                            Operand::Register(reg) => Some(reg),
+                            Operand::Literal(_) => None,
+                        });
+                    }
+
+                    if let Some(new) = Interval::apply(inst.op, old, rhs) {
+                        max.see(new, certain && !fails);
+                        let joined = if fails || looping { old.join(new) } else { new };
+                        env.set(inst.reg, joined);
+                    }
+                }
+                Statement::Halt { .. } if holds && !looping => {
+                    halted = Some(match halted {
+                        Some(halted) => halted.join(&env),
+                        None => env.clone(),
+                    });
+                    reachable = fails;
+                    certain = false;
+                }
+                _ => {}
+            }
+
+            states.push(reachable.then(|| env.clone()));
+        }
+
+        let last = match (halted, reachable) {
+            (Some(halted), true) => halted.join(&env),
+            (Some(halted), false) => halted,
+            (None, _) => env,
+        };
+
+        let mut largest = MaxBound::default();
+        for reg in possible.iter() {
+            largest.see(last.get(reg), existing.contains(reg));
+        }
+
+        Self {
+            states,
+            last,
+            largest: largest.bound(),
+            max: max.bound(),
+            faults,
+        }
+    }
+
+    /// Register ranges just before line `pc` runs, or `None` if it can't
+    /// be reached.
+    pub fn before(&self, pc: usize) -> Option<&Env<'a>> {
+        self.states[pc].as_ref()
+    }
+
+    /// Register ranges once line `pc` has run, or `None` if the program
+    /// can't carry on past it.
+    pub fn after(&self, pc: usize) -> Option<&Env<'a>> {
+        self.states[pc + 1].as_ref()
+    }
+
+    /// Register ranges when the program stops, whether by halting or
+    /// running off the end.
+    pub fn last(&self) -> &Env<'a> {
+        &self.last
+    }
+
+    /// Where `Cpu::largest_register` must end up, or `None` if no register
+    /// could ever be named.
+    pub fn largest_register(&self) -> Option<Interval> {
+        self.largest
+    }
+
+    /// Where `Cpu::max` must end up, or `None` if nothing could be written.
+    pub fn max(&self) -> Option<Interval> {
+        self.max
+    }
+
+    /// Lines that might stop the program with an error, and why.
+    pub fn faults(&self) -> &[(usize, Fault)] {
+        &self.faults
+    }
+
+    /// Whether the program is sure to run without an error.
+    pub fn is_safe(&self) -> bool {
+        self.faults.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let iv = Interval::new;
+        assert_eq!(
+            Interval::apply(Op::Inc, iv(0, 5), iv(-1, 1)),
+            Some(iv(-1, 6))
+        );
+        assert_eq!(
+            Interval::apply(Op::Dec, iv(0, 5), iv(-1, 1)),
+            Some(iv(-1, 6))
+        );
+        assert_eq!(
+            Interval::apply(Op::Mul, iv(-2, 3), iv(-4, 5)),
+            Some(iv(-12, 15))
+        );
+        assert_eq!(
+            Interval::apply(Op::Div, iv(-7, 9), iv(-2, 3)),
+            Some(iv(-9, 9))
+        );
+        assert_eq!(Interval::apply(Op::Div, iv(7, 9), iv(2, 3)), Some(iv(2, 4)));
+        assert_eq!(Interval::apply(Op::Div, iv(7, 9), iv(0, 0)), None);
+        assert_eq!(
+            Interval::apply(Op::Mod, iv(-7, 9), iv(3, 3)),
+            Some(iv(-2, 2))
+        );
+        assert_eq!(
+            Interval::apply(Op::Mod, iv(1, 2), iv(-5, 5)),
+            Some(iv(0, 2))
+        );
+        assert_eq!(Interval::apply(Op::Min, iv(0, 5), iv(2, 3)), Some(iv(0, 3)));
+        assert_eq!(Interval::apply(Op::Max, iv(0, 5), iv(2, 3)), Some(iv(2, 5)));
+        assert_eq!(
+            Interval::apply(Op::Inc, Interval::TOP, iv(-1, 1)),
+            Some(Interval::TOP)
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let iv = Interval::new;
+        assert_eq!(
+            Interval::compare(iv(0, 5), Cmp::Gt, iv(5, 5)),
+            (false, true)
+        );
+        assert_eq!(Interval::compare(iv(0, 5), Cmp::Ge, iv(5, 5)), (true, true));
+        assert_eq!(
+            Interval::compare(iv(6, 9), Cmp::Gt, iv(5, 5)),
+            (true, false)
+        );
+        assert_eq!(
+            Interval::compare(iv(3, 3), Cmp::Eq, iv(3, 3)),
+            (true, false)
+        );
+        assert_eq!(
+            Interval::compare(iv(3, 3), Cmp::Ne, iv(3, 3)),
+            (false, true)
+        );
+        assert_eq!(
+            Interval::compare(iv(0, 2), Cmp::Eq, iv(3, 4)),
+            (false, true)
+        );
+    }
+
+    fn interpret<'a>(input: &'a str, initial: &[(&'a str, Interval)]) -> Bounds<'a> {
+        let (_, program) = crate::day8::Cpu::parse_instructions(input).unwrap();
+        let mut env = Env::default();
+        for &(reg, range) in initial {
+            env.set(reg, range);
+        }
+        Bounds::with_initial(&program, env)
+    }
+
+    #[test]
+    fn test_states() {
+        let bounds = interpret(
+            "a inc 5 if b == 0\n\
+             b dec 3 if a > 1\n\
+             a inc 1 if b < 0 or c > 0\n\
+             c set a if a > 100",
+            &[],
+        );
+
+        assert_eq!(bounds.after(0).unwrap().get("a"), Interval::point(5));
+        assert_eq!(bounds.before(2).unwrap().get("b"), Interval::point(-3));
+        assert_eq!(bounds.after(2).unwrap().get("a"), Interval::point(6));
+        assert_eq!(bounds.last().get("c"), Interval::point(0));
+        assert_eq!(bounds.largest_register(), Some(Interval::point(6)));
+        assert_eq!(bounds.max(), Some(Interval::point(6)));
+        assert!(bounds.is_safe());
+    }
+
+    #[test]
+    fn test_either_way() {
+        let iv = Interval::new;
+        let bounds = interpret(
+            "a inc 1 if x > 0\n\
+             b dec 3 if a > 0\n\
+             c set b if b < 0\n\
+             c inc 10 if a == 0",
+            &[("x", iv(-5, 5))],
+        );
+
+        assert_eq!(bounds.last().get("a"), iv(0, 1));
+        assert_eq!(bounds.last().get("b"), iv(-3, 0));
+        assert_eq!(bounds.last().get("c"), iv(-3, 10));
+        assert_eq!(bounds.largest_register(), Some(iv(0, 10)));
+        assert_eq!(bounds.max(), Some(iv(-5, 10)));
+    }
+
+    #[test]
+    fn test_matches_execution() {
+        let input = "b inc 5 if a > 1\n\
+                     a inc 1 if b < 5\n\
+                     c dec -10 if a >= 1\n\
+                     c inc -20 if c == 10";
+        let bounds = interpret(input, &[]);
+        let mut cpu = crate::day8::Cpu::new(input).unwrap();
+        cpu.exec().unwrap();
+
+        assert_eq!(
+            bounds.largest_register(),
+            Some(Interval::point(cpu.largest_register().unwrap()))
+        );
+        assert_eq!(bounds.max(), Some(Interval::point(cpu.max().unwrap())));
+    }
+
+    #[test]
+    fn test_halt() {
+        let iv = Interval::new;
+        let bounds = interpret(
+            "a inc 4 if a == 0\n\
+             halt if x > 0\n\
+             a inc 4 if a == 4\n\
+             halt\n\
+             a inc 100 if a == 8",
+            &[("x", iv(-5, 5))],
+        );
+
+        assert_eq!(bounds.last().get("a"), iv(4, 8));
+        assert!(bounds.before(4).is_none());
+        assert_eq!(bounds.max(), Some(iv(4, 8)));
+    }
+
+    #[test]
+    fn test_faults() {
+        let bounds = interpret(
+            "a set 2147483647 if a == 0\n\
+             a inc 1 if b > 0\n\
+             b div a if a < 0\n\
+             c mod b if a > 0\n\
+             d mul 1000 if a > 0",
+            &[("b", Interval::new(0, 1))],
+        );
+
+        assert_eq!(
+            bounds.faults(),
+            &[(1, Fault::Overflow), (3, Fault::DivideByZero)]
+        );
+        assert!(!bounds.is_safe());
+
+        // the jump means anything could have happened to a by line 2
+        let bounds = interpret(
+            "top:\n\
+             a inc 1 if a < 10\n\
+             b inc 1 if a > 100\n\
+             jmp top if a < 10",
+            &[],
+        );
+        assert_eq!(
+            bounds.faults(),
+            &[(1, Fault::Overflow), (2, Fault::Overflow)]
+        );
+        assert_eq!(bounds.before(2).unwrap().get("a"), Interval::TOP);
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
+use super::{Cpu, ExecError, Statement};
+
+/// How long a candidate gets to run. Dropping lines can turn a loop that
+/// used to exit into one that never does, and those shouldn't each burn
+/// through the full default limit.
+pub const CANDIDATE_STEP_LIMIT: usize = 1_000_000;
+
+/// Something a program does that minimizing should keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    /// Runs to the end with `largest_register` at this value.
+    Largest(i32),
+    /// Runs to the end with `max` at this value.
+    Max(i32),
+    Overflow,
+    DivideByZero,
+    /// Doesn't halt within `CANDIDATE_STEP_LIMIT` steps.
+    StepLimit,
+}
+
+impl Property {
+    /// Whether `program` has the property. Programs that don't load, such
+    /// as ones whose jumps lost their label, never do.
+    pub fn holds(self, program: &[Statement]) -> bool {
+        let Ok(mut cpu) = Cpu::with_instructions(program.to_vec()) else {
+            return false;
+        };
+        cpu.set_step_limit(CANDIDATE_STEP_LIMIT);
+
+        match (self, cpu.exec()) {
+            (Property::Largest(val), Ok(())) => cpu.largest_register() == Some(val),
+            (Property::Max(val), Ok(())) => cpu.max() == Some(val),
+            (Property::Overflow, Err(ExecError::Overflow { .. })) => true,
+            (Property::DivideByZero, Err(ExecError::DivideByZero { .. })) => true,
+            (Property::StepLimit, Err(ExecError::StepLimit { .. })) => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Property {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = |val: &str| {
+            val.parse()
+                .map_err(|_| anyhow!("Not a register value: {}", val))
+        };
+
+        match s.split_once('=') {
+            Some(("largest", val)) => Ok(Property::Largest(value(val)?)),
+            Some(("max", val)) => Ok(Property::Max(value(val)?)),
+            None if s == "overflow" => Ok(Property::Overflow),
+            None if s == "divzero" => Ok(Property::DivideByZero),
+            None if s == "steplimit" => Ok(Property::StepLimit),
+            _ => Err(anyhow!("Unknown property: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Property::Largest(val) => write!(f, "largest={}", val),
+            Property::Max(val) => write!(f, "max={}", val),
+            Property::Overflow => f.write_str("overflow"),
+            Property::DivideByZero => f.write_str("divzero"),
+            Property::StepLimit => f.write_str("steplimit"),
+        }
+    }
+}
+
+/// Shrinks `program` to a subsequence that still passes `test`, using
+/// delta debugging: try ever smaller chunks of the program, and ever
+/// smaller pieces to leave out, until removing any single line would make
+/// the test fail. Returns `None` if `program` doesn't pass to begin with.
+pub fn minimize<'a>(
+    program: &[Statement<'a>],
+    mut test: impl FnMut(&[Statement<'a>]) -> bool,
+) -> Option<Vec<Statement<'a>>> {
+    if !test(program) {
+        return None;
+    }
+
+    let mut current = program.to_vec();
+    let mut parts = 2;
+    while current.len() >= 2 {
+        let size = current.len().div_ceil(parts);
+        let chunks: Vec<_> = (0..current.len()).step_by(size).collect();
+
+        let subset = chunks
+            .iter()
+            .map(|&start| current[start..current.len().min(start + size)].to_vec())
+            .find(|subset| test(subset));
+        if let Some(subset) = subset {
+            current = subset;
+            parts = 2;
+            continue;
+        }
+
+        let complement = chunks
+            .iter()
+            .map(|&start| {
+                let mut rest = current[..start].to_vec();
+                rest.extend_from_slice(&current[current.len().min(start + size)..]);
+                rest
+            })
+            .find(|rest| test(rest));
+        if let Some(rest) = complement {
+            current = rest;
+            parts = (parts - 1).max(2);
+            continue;
+        }
+
+        if parts >= current.len() {
+            break;
+        }
+        parts = (parts * 2).min(current.len());
+    }
+
+    Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shrink(input: &str, property: Property) -> Option<String> {
+        let (_, program) = Cpu::parse_instructions(input).unwrap();
+        minimize(&program, |candidate| property.holds(candidate))
+            .map(|program| Cpu::format_instructions(&program))
+    }
+
+    #[test]
+    fn test_largest() {
+        assert_eq!(
+            shrink(
+                "b inc 5 if a > 1\n\
+                 a inc 1 if b < 5\n\
+                 c dec -10 if a >= 1\n\
+                 c inc -20 if c == 10",
+                Property::Largest(1),
+            ),
+            Some("a inc 1 if b < 5\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_max() {
+        assert_eq!(
+            shrink(
+                "b inc 5 if a > 1\n\
+                 a inc 1 if b < 5\n\
+                 c dec -10 if a >= 1\n\
+                 c inc -20 if c == 10",
+                Property::Max(10),
+            ),
+            Some("a inc 1 if b < 5\nc dec -10 if a >= 1\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(
+            shrink(
+                "x inc 3 if y == 0\n\
+                 a set 1000000 if a == 0\n\
+                 y dec 7 if x > 1\n\
+                 a mul 1000 if a > 0\n\
+                 z inc 1 if a > 0\n\
+                 a mul a if z == 1\n\
+                 b inc 1 if a != 0",
+                Property::Overflow,
+            ),
+            Some(
+                "a set 1000000 if a == 0\n\
+                 z inc 1 if a > 0\n\
+                 a mul a if z == 1\n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_keeps_labels() {
+        // the loop needs its label, and the jump back, to keep spinning
+        assert_eq!(
+            shrink(
+                "a inc 1 if b == 0\n\
+                 top:\n\
+                 b inc 1 if a > 0\n\
+                 c inc 2 if b > 3\n\
+                 jmp top if a > 0",
+                Property::StepLimit,
+            ),
+            Some("a inc 1 if b == 0\ntop:\njmp top if a > 0\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_not_holding() {
+        assert_eq!(shrink("a inc 1 if a == 0", Property::Max(2)), None);
+    }
+
+    #[test]
+    fn test_parse_property() {
+        for text in ["largest=5", "max=-3", "overflow", "divzero", "steplimit"] {
+            let property: Property = text.parse().unwrap();
+            assert_eq!(property.to_string(), text);
+        }
+
+        assert!("largest=x".parse::<Property>().is_err());
+        assert!("fastest".parse::<Property>().is_err());
+    }
+}
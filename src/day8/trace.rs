@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::io;
+
+use super::value::Value;
+use super::{Cpu, ExecError, Statement};
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event<'a, V = i32> {
+    pub step: usize,
+    pub pc: usize,
+    /// Whether the condition held, so the update, jump or halt happened.
+    /// Labels and unconditional jumps always count as taken.
+    pub taken: bool,
+    /// Every register looked at, in evaluation order, with the value seen.
+    pub reads: Vec<(&'a str, V)>,
+    pub write: Option<Write<'a, V>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Write<'a, V = i32> {
+    pub reg: &'a str,
+    pub before: V,
+    pub after: V,
+}
+
+/// A record of everything a `Cpu` did while it was being traced, and of
+/// each register's values over time.
+pub struct Trace<'a, V = i32> {
+    events: Vec<Event<'a, V>>,
+    history: HashMap<&'a str, Vec<(usize, V)>>,
+}
+
+impl<'a, V: Value> Cpu<'a, V> {
+    /// Like `step`, but describes what the instruction did, or returns
+    /// `None` once the program has halted.
+    pub fn step_event(&mut self) -> Result<Option<Event<'a, V>>, ExecError> {
+        let step = self.steps;
+        let mut reads = Vec::new();
+
+        Ok(self.step_inner(Some(&mut reads))?.map(|outcome| Event {
+            step,
+            pc: outcome.pc,
+            taken: outcome.taken,
+            reads,
+            write: outcome
+                .write
+                .map(|(reg, before, after)| Write { reg, before, after }),
+        }))
+    }
+}
+
+impl<V: Value> Default for Trace<'_, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, V: Value> Trace<'a, V> {
+    pub fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn step(&mut self, cpu: &mut Cpu<'a, V>) -> Result<bool, ExecError> {
+        let Some(event) = cpu.step_event()? else {
+            return Ok(false);
+        };
+
+        if let Some(write) = &event.write {
+            self.history
+                .entry(write.reg)
+                .or_default()
+                .push((event.step, write.after.clone()));
+        }
+        self.events.push(event);
+
+        Ok(true)
+    }
+
+    /// Runs `cpu` until it halts, recording as it goes. On error the trace
+    /// still holds everything up to the failing instruction.
+    pub fn run(&mut self, cpu: &mut Cpu<'a, V>) -> Result<(), ExecError> {
+        while self.step(cpu)? {}
+        Ok(())
+    }
+
+    pub fn events(&self) -> &[Event<'a, V>] {
+        &self.events
+    }
+
+    /// The step each write to `reg` happened at, with the value written.
+    pub fn history(&self, reg: &str) -> &[(usize, V)] {
+        self.history.get(reg).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first write of the highest value any register reached.
+    pub fn max_event(&self) -> Option<&Event<'a, V>> {
+        let mut best: Option<(&Event<'a, V>, &V)> = None;
+        for event in self.events.iter() {
+            if let Some(write) = &event.write {
+                if best.is_none_or(|(_, max)| write.after > *max) {
+                    best = Some((event, &write.after));
+                }
+            }
+        }
+
+        best.map(|(event, _)| event)
+    }
+
+    /// Lines of `program`, other than labels, that never took effect while
+    /// traced: either their condition never held or they never ran.
+    pub fn never_fired(&self, program: &[Statement]) -> Vec<usize> {
+        let mut fired = vec![false; program.len()];
+        for event in self.events.iter().filter(|event| event.taken) {
+            fired[event.pc] = true;
+        }
+
+        program
+            .iter()
+            .enumerate()
+            .filter(|&(pc, stmt)| !fired[pc] && !matches!(stmt, Statement::Label(_)))
+            .map(|(pc, _)| pc)
+            .collect()
+    }
+
+    /// Writes one JSON object per event. Register names are plain letters
+    /// and values print as bare integers, so nothing needs escaping.
+    pub fn write_json_lines(&self, mut out: impl io::Write) -> io::Result<()> {
+        for event in self.events.iter() {
+            let reads = event
+                .reads
+                .iter()
+                .map(|(reg, val)| format!("{{\"reg\":\"{}\",\"value\":{}}}", reg, val))
+                .collect::<Vec<_>>()
+                .join(",");
+            let write = match &event.write {
+                Some(write) => format!(
+                    "{{\"reg\":\"{}\",\"before\":{},\"after\":{}}}",
+                    write.reg, write.before, write.after
+                ),
+                None => "null".to_string(),
+            };
+
+            writeln!(
+                out,
+                "{{\"step\":{},\"pc\":{},\"taken\":{},\"reads\":[{}],\"write\":{}}}",
+                event.step, event.pc, event.taken, reads, write
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "b inc 5 if a > 1\n\
+                           a inc 1 if b < 5\n\
+                           c dec -10 if a >= 1\n\
+                           c inc -20 if c == 10";
+
+    #[test]
+    fn test_events() {
+        let mut cpu = Cpu::new(EXAMPLE).unwrap();
+        let mut trace = Trace::new();
+        trace.run(&mut cpu).unwrap();
+
+        let events = trace.events();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[0],
+            Event {
+                step: 0,
+                pc: 0,
+                taken: false,
+                reads: vec![("a", 0)],
+                write: None,
+            }
+        );
+        assert_eq!(
+            events[3],
+            Event {
+                step: 3,
+                pc: 3,
+                taken: true,
+                reads: vec![("c", 10)],
+                write: Some(Write {
+                    reg: "c",
+                    before: 10,
+                    after: -10,
+                }),
+            }
+        );
+
+        assert_eq!(trace.history("c"), &[(2, 10), (3, -10)]);
+        assert_eq!(trace.history("b"), &[]);
+        assert_eq!(trace.max_event().unwrap().pc, 2);
+        assert_eq!(trace.never_fired(cpu.instructions()), vec![0]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let mut cpu = Cpu::new(
+            "top:\n\
+             a inc 1 if a < 3 and b == 0\n\
+             jmp top if a < 3\n\
+             halt if a > 5\n\
+             b set a if a == 3",
+        )
+        .unwrap();
+        let mut trace = Trace::new();
+        trace.run(&mut cpu).unwrap();
+
+        assert_eq!(trace.history("a"), &[(1, 1), (3, 2), (5, 3)]);
+        assert_eq!(trace.never_fired(cpu.instructions()), vec![3]);
+        assert_eq!(trace.events()[8].reads, vec![("a", 3), ("a", 3)]);
+    }
+
+    #[test]
+    fn test_partial_trace() {
+        let mut cpu = Cpu::new("a inc 1 if a == 0\nb div 0 if a > 0").unwrap();
+        let mut trace = Trace::new();
+        assert_eq!(trace.run(&mut cpu), Err(ExecError::DivideByZero { pc: 1 }));
+        assert_eq!(trace.events().len(), 1);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut cpu = Cpu::new(EXAMPLE).unwrap();
+        let mut trace = Trace::new();
+        trace.run(&mut cpu).unwrap();
+
+        let mut out = Vec::new();
+        trace.write_json_lines(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            r#"{"step":0,"pc":0,"taken":false,"reads":[{"reg":"a","value":0}],"write":null}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"step":2,"pc":2,"taken":true,"reads":[{"reg":"a","value":1}],"write":{"reg":"c","before":0,"after":10}}"#
+        );
+    }
+}
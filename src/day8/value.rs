@@ -0,0 +1,193 @@
+use std::fmt;
+use std::num::Saturating;
+
+use num_bigint::BigInt;
+
+/// What can go wrong applying an `Op`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    DivideByZero,
+    Overflow,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fault::DivideByZero => "division by zero",
+            Fault::Overflow => "arithmetic overflow",
+        })
+    }
+}
+
+/// The number type registers hold, which decides what happens when a
+/// result doesn't fit: `i32` and `i64` report an overflow, `Saturating<i32>`
+/// clamps to the nearest bound, and `BigInt` never runs out of room.
+pub trait Value: Clone + Ord + Default + fmt::Debug + fmt::Display {
+    fn from_i32(val: i32) -> Self;
+
+    // Each of these returns `None` when the result is out of range. Division
+    // by zero is caught before they are called.
+    fn add(&self, rhs: &Self) -> Option<Self>;
+    fn sub(&self, rhs: &Self) -> Option<Self>;
+    fn mul(&self, rhs: &Self) -> Option<Self>;
+    fn div(&self, rhs: &Self) -> Option<Self>;
+    fn rem(&self, rhs: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Value for i32 {
+    fn from_i32(val: i32) -> Self {
+        val
+    }
+
+    fn add(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+
+    fn sub(&self, rhs: &Self) -> Option<Self> {
+        self.checked_sub(*rhs)
+    }
+
+    fn mul(&self, rhs: &Self) -> Option<Self> {
+        self.checked_mul(*rhs)
+    }
+
+    fn div(&self, rhs: &Self) -> Option<Self> {
+        self.checked_div(*rhs)
+    }
+
+    fn rem(&self, rhs: &Self) -> Option<Self> {
+        self.checked_rem(*rhs)
+    }
+}
+
+impl Value for i64 {
+    fn from_i32(val: i32) -> Self {
+        val.into()
+    }
+
+    fn add(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+
+    fn sub(&self, rhs: &Self) -> Option<Self> {
+        self.checked_sub(*rhs)
+    }
+
+    fn mul(&self, rhs: &Self) -> Option<Self> {
+        self.checked_mul(*rhs)
+    }
+
+    fn div(&self, rhs: &Self) -> Option<Self> {
+        self.checked_div(*rhs)
+    }
+
+    fn rem(&self, rhs: &Self) -> Option<Self> {
+        self.checked_rem(*rhs)
+    }
+}
+
+impl Value for Saturating<i32> {
+    fn from_i32(val: i32) -> Self {
+        Saturating(val)
+    }
+
+    fn add(&self, rhs: &Self) -> Option<Self> {
+        Some(*self + *rhs)
+    }
+
+    fn sub(&self, rhs: &Self) -> Option<Self> {
+        Some(*self - *rhs)
+    }
+
+    fn mul(&self, rhs: &Self) -> Option<Self> {
+        Some(*self * *rhs)
+    }
+
+    fn div(&self, rhs: &Self) -> Option<Self> {
+        Some(*self / *rhs)
+    }
+
+    // i32::MIN % -1 is mathematically 0, it only overflows on the way there
+    fn rem(&self, rhs: &Self) -> Option<Self> {
+        Some(Saturating(self.0.checked_rem(rhs.0).unwrap_or(0)))
+    }
+}
+
+impl Value for BigInt {
+    fn from_i32(val: i32) -> Self {
+        val.into()
+    }
+
+    fn add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn sub(&self, rhs: &Self) -> Option<Self> {
+        Some(self - rhs)
+    }
+
+    fn mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn div(&self, rhs: &Self) -> Option<Self> {
+        Some(self / rhs)
+    }
+
+    fn rem(&self, rhs: &Self) -> Option<Self> {
+        Some(self % rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(i32::MAX.add(&1), None);
+        assert_eq!(i32::MIN.div(&-1), None);
+        assert_eq!(i64::from(i32::MAX).add(&1), Some(1 << 31));
+        assert_eq!(i64::MIN.sub(&1), None);
+    }
+
+    #[test]
+    fn test_saturating() {
+        assert_eq!(
+            Saturating(i32::MAX).add(&Saturating(1)),
+            Some(Saturating(i32::MAX))
+        );
+        assert_eq!(
+            Saturating(i32::MIN).mul(&Saturating(2)),
+            Some(Saturating(i32::MIN))
+        );
+        assert_eq!(
+            Saturating(i32::MIN).div(&Saturating(-1)),
+            Some(Saturating(i32::MAX))
+        );
+        assert_eq!(
+            Saturating(i32::MIN).rem(&Saturating(-1)),
+            Some(Saturating(0))
+        );
+    }
+
+    #[test]
+    fn test_bigint() {
+        let big = BigInt::from_i32(i32::MAX)
+            .mul(&BigInt::from_i32(i32::MAX))
+            .unwrap();
+        assert_eq!(big.to_string(), "4611686014132420609");
+        assert_eq!(
+            BigInt::from_i32(-7).div(&BigInt::from_i32(2)),
+            Some(BigInt::from(-3))
+        );
+        assert_eq!(
+            BigInt::from_i32(-7).rem(&BigInt::from_i32(2)),
+            Some(BigInt::from(-1))
+        );
+    }
+}
@@ -1,89 +1,263 @@
+use std::fmt;
+use std::ops::Range;
+
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag},
     character::complete::{anychar, char},
-    combinator::{map, opt},
-    multi::{fold_many0, many0},
-    sequence::{delimited, pair, terminated},
-    IResult,
+    combinator::{map, opt, recognize},
+    error::{Error, ErrorKind},
+    multi::fold_many0,
+    sequence::pair,
+    IResult, Offset,
 };
 
-#[derive(Debug, PartialEq)]
-enum Thing {
-    Group(Vec<Thing>),
-    Garbage(i32),
+pub mod parallel;
+pub mod query;
+pub mod recover;
+pub mod serialize;
+pub mod stream;
+
+/// A node of a parsed stream. Spans are byte offsets into the text handed
+/// to `Thing::parse`.
+pub enum Thing {
+    Group {
+        span: Range<usize>,
+        children: Vec<Thing>,
+    },
+    Garbage {
+        span: Range<usize>,
+        /// What's left between the angle brackets once every `!` and the
+        /// character after it are taken out.
+        text: String,
+        /// Where each `!` that cancelled the next character sits.
+        cancelled: Vec<usize>,
+    },
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Cancel(&'a str),
 }
 
 impl Thing {
-    fn parse(input: &str) -> IResult<&str, Self> {
+    pub fn parse(input: &str) -> IResult<&str, Self> {
         alt((Self::parse_garbage, Self::parse_group))(input)
     }
 
     fn parse_garbage(input: &str) -> IResult<&str, Self> {
-        map(
-            delimited(
-                tag("<"),
-                fold_many0(
-                    alt((
-                        map(pair(char('!'), anychar), |_| 0),
-                        map(is_not("!>"), |s: &str| s.len() as i32),
-                    )),
-                    || 0,
-                    |sum, item| sum + item,
-                ),
-                tag(">"),
-            ),
-            Self::Garbage,
+        Self::parse_garbage_in(input, input)
+    }
+
+    // Parses garbage at the start of `input`, which is somewhere inside
+    // `source`; spans and positions count from the start of `source`.
+    fn parse_garbage_in<'a>(source: &str, input: &'a str) -> IResult<&'a str, Self> {
+        let (body, _) = tag("<")(input)?;
+        let (rest, (text, cancelled)) = Self::garbage_body(source, body)?;
+        let (rest, _) = tag(">")(rest)?;
+
+        Ok((
+            rest,
+            Self::Garbage {
+                span: source.offset(input)..source.offset(rest),
+                text,
+                cancelled,
+            },
+        ))
+    }
+
+    // Everything up to the `>` that ends some garbage, or to the end of
+    // the input if there isn't one. This part never fails.
+    fn garbage_body<'a>(source: &str, input: &'a str) -> IResult<&'a str, (String, Vec<usize>)> {
+        fold_many0(
+            alt((
+                map(recognize(pair(char('!'), anychar)), Piece::Cancel),
+                map(is_not("!>"), Piece::Text),
+            )),
+            || (String::new(), Vec::new()),
+            |(mut text, mut cancelled), piece| {
+                match piece {
+                    Piece::Text(s) => text.push_str(s),
+                    Piece::Cancel(s) => cancelled.push(source.offset(s)),
+                }
+                (text, cancelled)
+            },
         )(input)
     }
 
+    // Groups nest as deep as the input likes, so rather than recursing,
+    // the groups still open are kept on a stack, each with where it
+    // started and its children so far.
     fn parse_group(input: &str) -> IResult<&str, Self> {
-        map(
-            delimited(
-                tag("{"),
-                many0(terminated(Thing::parse, opt(char(',')))),
-                tag("}"),
-            ),
-            Self::Group,
-        )(input)
+        let mut open: Vec<(usize, Vec<Thing>)> = Vec::new();
+        let mut rest = input;
+
+        loop {
+            let start = input.offset(rest);
+            let (after, thing) = if let Some(after) = rest.strip_prefix('{') {
+                open.push((start, Vec::new()));
+                rest = after;
+                continue;
+            } else if let (Some(after), Some(_)) = (rest.strip_prefix('}'), open.last()) {
+                let (start, children) = open.pop().unwrap();
+                let span = start..input.offset(after);
+                (after, Thing::Group { span, children })
+            } else if open.is_empty() {
+                return Err(nom::Err::Error(Error::new(rest, ErrorKind::Char)));
+            } else {
+                Self::parse_garbage_in(input, rest)?
+            };
+
+            match open.last_mut() {
+                None => return Ok((after, thing)),
+                Some((_, children)) => {
+                    children.push(thing);
+                    (rest, _) = opt(char(','))(after)?;
+                }
+            }
+        }
     }
 
-    fn score(&self) -> i32 {
-        self._score(1)
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Thing::Group { span, .. } | Thing::Garbage { span, .. } => span.clone(),
+        }
     }
 
-    fn _score(&self, depth: i32) -> i32 {
+    /// The things directly inside a group; garbage has none.
+    pub fn children(&self) -> &[Thing] {
         match self {
-            Thing::Garbage(_) => 0,
-            Thing::Group(things) => {
-                depth
-                    + things
-                        .iter()
-                        .map(|thing| thing._score(depth + 1))
-                        .sum::<i32>()
+            Thing::Group { children, .. } => children,
+            Thing::Garbage { .. } => &[],
+        }
+    }
+
+    pub fn score(&self) -> u64 {
+        let mut score = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((thing, depth)) = stack.pop() {
+            if let Thing::Group { children, .. } = thing {
+                score += depth;
+                stack.extend(children.iter().map(|thing| (thing, depth + 1)));
             }
         }
+
+        score
     }
 
-    fn garbage_count(&self) -> i32 {
-        match self {
-            Thing::Garbage(count) => *count,
-            Thing::Group(things) => things
-                .iter()
-                .map(|thing| thing.garbage_count())
-                .sum::<i32>(),
+    pub fn garbage_count(&self) -> u64 {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(thing) = stack.pop() {
+            match thing {
+                Thing::Garbage { text, .. } => count += text.len() as u64,
+                Thing::Group { children, .. } => stack.extend(children.iter()),
+            }
+        }
+
+        count
+    }
+}
+
+// The derived traits would recurse once per level of nesting, so these
+// keep what's left to do on a heap stack instead.
+
+impl PartialEq for Thing {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (
+                    Thing::Group { span, children },
+                    Thing::Group {
+                        span: other_span,
+                        children: other_children,
+                    },
+                ) if span == other_span && children.len() == other_children.len() => {
+                    stack.extend(children.iter().zip(other_children));
+                }
+                (
+                    Thing::Garbage {
+                        span,
+                        text,
+                        cancelled,
+                    },
+                    Thing::Garbage {
+                        span: other_span,
+                        text: other_text,
+                        cancelled: other_cancelled,
+                    },
+                ) if span == other_span && text == other_text && cancelled == other_cancelled => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Written as the derived one would be, all on one line even for `{:#?}`.
+impl fmt::Debug for Thing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'a> {
+            Thing(&'a Thing),
+            Text(&'static str),
+        }
+
+        let mut stack = vec![Item::Thing(self)];
+        while let Some(item) = stack.pop() {
+            match item {
+                Item::Thing(Thing::Group { span, children }) => {
+                    write!(f, "Group {{ span: {:?}, children: [", span)?;
+                    stack.push(Item::Text("] }"));
+                    for (i, child) in children.iter().enumerate().rev() {
+                        stack.push(Item::Thing(child));
+                        if i > 0 {
+                            stack.push(Item::Text(", "));
+                        }
+                    }
+                }
+                Item::Thing(Thing::Garbage {
+                    span,
+                    text,
+                    cancelled,
+                }) => write!(
+                    f,
+                    "Garbage {{ span: {:?}, text: {:?}, cancelled: {:?} }}",
+                    span, text, cancelled
+                )?,
+                Item::Text(text) => f.write_str(text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Children are moved out onto a heap stack and dropped from there.
+impl Drop for Thing {
+    fn drop(&mut self) {
+        let Thing::Group { children, .. } = self else {
+            return;
+        };
+
+        let mut stack = std::mem::take(children);
+        while let Some(mut thing) = stack.pop() {
+            if let Thing::Group { children, .. } = &mut thing {
+                stack.append(children);
+            }
         }
     }
 }
 
 #[aoc(day9, part1)]
-pub fn solve_part1(input: &str) -> i32 {
+pub fn solve_part1(input: &str) -> u64 {
     let (_, thing) = Thing::parse(input).unwrap();
     thing.score()
 }
 
 #[aoc(day9, part2)]
-pub fn solve_part2(input: &str) -> i32 {
+pub fn solve_part2(input: &str) -> u64 {
     let (_, thing) = Thing::parse(input).unwrap();
     thing.garbage_count()
 }
@@ -92,100 +266,122 @@ pub fn solve_part2(input: &str) -> i32 {
 mod tests {
     use super::*;
 
+    // The tree without spans or garbage text, to compare against.
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Group(Vec<Shape>),
+        Garbage(usize),
+    }
+
+    fn shape(thing: &Thing) -> Shape {
+        match thing {
+            Thing::Group { children, .. } => Shape::Group(children.iter().map(shape).collect()),
+            Thing::Garbage { text, .. } => Shape::Garbage(text.len()),
+        }
+    }
+
+    fn parse(input: &str) -> Shape {
+        let (rest, thing) = Thing::parse(input).unwrap();
+        assert_eq!(rest, "");
+        shape(&thing)
+    }
+
     #[test]
     fn examples_part1_garbage() {
-        assert_eq!(Thing::parse_garbage("<>"), Ok(("", Thing::Garbage(0))));
-        assert_eq!(
-            Thing::parse_garbage("<random characters>"),
-            Ok(("", Thing::Garbage(17)))
-        );
-        assert_eq!(Thing::parse_garbage("<<<<>"), Ok(("", Thing::Garbage(3))));
-        assert_eq!(Thing::parse_garbage("<{!>}>"), Ok(("", Thing::Garbage(2))));
-        assert_eq!(Thing::parse_garbage("<!!>"), Ok(("", Thing::Garbage(0))));
-        assert_eq!(Thing::parse_garbage("<!!!>>"), Ok(("", Thing::Garbage(0))));
-        assert_eq!(
-            Thing::parse_garbage("<{o\"i!a,<{i<a>"),
-            Ok(("", Thing::Garbage(10)))
-        );
+        assert_eq!(parse("<>"), Shape::Garbage(0));
+        assert_eq!(parse("<random characters>"), Shape::Garbage(17));
+        assert_eq!(parse("<<<<>"), Shape::Garbage(3));
+        assert_eq!(parse("<{!>}>"), Shape::Garbage(2));
+        assert_eq!(parse("<!!>"), Shape::Garbage(0));
+        assert_eq!(parse("<!!!>>"), Shape::Garbage(0));
+        assert_eq!(parse("<{o\"i!a,<{i<a>"), Shape::Garbage(10));
     }
 
     #[test]
     fn examples_part1_groups() {
         // {}, 1 group.
-        assert_eq!(Thing::parse("{}"), Ok(("", Thing::Group(vec![]))));
+        assert_eq!(parse("{}"), Shape::Group(vec![]));
 
         // {{{}}}, 3 groups.
         assert_eq!(
-            Thing::parse("{{{}}}"),
-            Ok((
-                "",
-                Thing::Group(vec![Thing::Group(vec![Thing::Group(vec![])])])
-            ))
+            parse("{{{}}}"),
+            Shape::Group(vec![Shape::Group(vec![Shape::Group(vec![])])])
         );
 
         // {{},{}}, also 3 groups.
         assert_eq!(
-            Thing::parse("{{},{}}"),
-            Ok((
-                "",
-                Thing::Group(vec![Thing::Group(vec![]), Thing::Group(vec![])])
-            ))
+            parse("{{},{}}"),
+            Shape::Group(vec![Shape::Group(vec![]), Shape::Group(vec![])])
         );
 
         // {{{},{},{{}}}}, 6 groups.
         assert_eq!(
-            Thing::parse("{{{},{},{{}}}}"),
-            Ok((
-                "",
-                Thing::Group(vec![Thing::Group(vec![
-                    Thing::Group(vec![]),
-                    Thing::Group(vec![]),
-                    Thing::Group(vec![Thing::Group(vec![])])
-                ])])
-            ))
+            parse("{{{},{},{{}}}}"),
+            Shape::Group(vec![Shape::Group(vec![
+                Shape::Group(vec![]),
+                Shape::Group(vec![]),
+                Shape::Group(vec![Shape::Group(vec![])])
+            ])])
         );
 
         // {<{},{},{{}}>}, 1 group (which itself contains garbage).
         assert_eq!(
-            Thing::parse("{<{},{},{{}}>}"),
-            Ok(("", Thing::Group(vec![Thing::Garbage(10)])))
+            parse("{<{},{},{{}}>}"),
+            Shape::Group(vec![Shape::Garbage(10)])
         );
 
         // {<a>,<a>,<a>,<a>}, 1 group.
         assert_eq!(
-            Thing::parse("{<a>,<a>,<a>,<a>}"),
-            Ok((
-                "",
-                Thing::Group(vec![
-                    Thing::Garbage(1),
-                    Thing::Garbage(1),
-                    Thing::Garbage(1),
-                    Thing::Garbage(1)
-                ])
-            ))
+            parse("{<a>,<a>,<a>,<a>}"),
+            Shape::Group(vec![
+                Shape::Garbage(1),
+                Shape::Garbage(1),
+                Shape::Garbage(1),
+                Shape::Garbage(1)
+            ])
         );
 
         // {{<a>},{<a>},{<a>},{<a>}}, 5 groups.
         assert_eq!(
-            Thing::parse("{{<a>},{<a>},{<a>},{<a>}}"),
-            Ok((
-                "",
-                Thing::Group(vec![
-                    Thing::Group(vec![Thing::Garbage(1)]),
-                    Thing::Group(vec![Thing::Garbage(1)]),
-                    Thing::Group(vec![Thing::Garbage(1)]),
-                    Thing::Group(vec![Thing::Garbage(1)]),
-                ])
-            ))
+            parse("{{<a>},{<a>},{<a>},{<a>}}"),
+            Shape::Group(vec![
+                Shape::Group(vec![Shape::Garbage(1)]),
+                Shape::Group(vec![Shape::Garbage(1)]),
+                Shape::Group(vec![Shape::Garbage(1)]),
+                Shape::Group(vec![Shape::Garbage(1)]),
+            ])
         );
 
         // {{<!>},{<!>},{<!>},{<a>}}, 2 groups (since all but the last > are canceled).
         assert_eq!(
-            Thing::parse("{{<!>},{<!>},{<!>},{<a>}}"),
-            Ok((
-                "",
-                Thing::Group(vec![Thing::Group(vec![Thing::Garbage(13)])])
-            ))
+            parse("{{<!>},{<!>},{<!>},{<a>}}"),
+            Shape::Group(vec![Shape::Group(vec![Shape::Garbage(13)])])
+        );
+    }
+
+    #[test]
+    fn spans_and_text() {
+        let input = "{{<a!>b>},<!!c>}";
+        let (_, thing) = Thing::parse(input).unwrap();
+
+        assert_eq!(thing.span(), 0..input.len());
+        let inner = &thing.children()[0];
+        assert_eq!(&input[inner.span()], "{<a!>b>}");
+        assert_eq!(
+            inner.children()[0],
+            Thing::Garbage {
+                span: 2..8,
+                text: "ab".to_string(),
+                cancelled: vec![4],
+            }
+        );
+        assert_eq!(
+            thing.children()[1],
+            Thing::Garbage {
+                span: 10..15,
+                text: "c".to_string(),
+                cancelled: vec![11],
+            }
         );
     }
 
@@ -211,4 +407,32 @@ mod tests {
         assert_eq!(solve_part2("<!!!>>"), 0);
         assert_eq!(solve_part2("<{o\"i!a,<{i<a>"), 10);
     }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 500_000;
+        let input = format!("{}<ab>{}", "{".repeat(depth), "},".repeat(depth));
+        let input = input.trim_end_matches(',');
+
+        assert_eq!(solve_part1(input), (depth * (depth + 1) / 2) as u64);
+        assert_eq!(solve_part2(input), 2);
+
+        let (_, thing) = Thing::parse(input).unwrap();
+        let (_, again) = Thing::parse(input).unwrap();
+        assert!(thing == again);
+        let other = input.replacen("<ab>", "<ac>", 1);
+        let (_, other) = Thing::parse(&other).unwrap();
+        assert!(thing != other);
+        assert_eq!(format!("{:?}", thing).matches("Group").count(), depth);
+    }
+
+    #[test]
+    fn debug_format() {
+        let (_, thing) = Thing::parse("{{},<a!b>}").unwrap();
+        assert_eq!(
+            format!("{:?}", thing),
+            "Group { span: 0..10, children: [Group { span: 1..3, children: [] }, \
+             Garbage { span: 4..9, text: \"a\", cancelled: [6] }] }"
+        );
+    }
 }
@@ -0,0 +1,291 @@
+use std::io::{self, Read};
+use std::thread;
+
+use super::stream::{Counts, Effect, State};
+
+/// How much of a reader `scan_parallel` holds at once.
+const BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+const STATES: [State; 4] = [
+    State::Group,
+    State::Garbage,
+    State::Cancel,
+    State::Cancelled,
+];
+
+// What a stretch of input does when entered in one particular state.
+// Depths are relative to wherever the stretch starts, so `score` is what
+// it adds when that is 0; each `{` adds one more per level deeper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    exit: State,
+    delta: i64,
+    opens: i64,
+    score: i64,
+    garbage: u64,
+}
+
+impl Run {
+    fn feed(&mut self, byte: u8) {
+        let effect;
+        (self.exit, effect) = self.exit.next(byte);
+        match effect {
+            Effect::Open => {
+                self.delta += 1;
+                self.opens += 1;
+                self.score += self.delta;
+            }
+            Effect::Close => self.delta -= 1,
+            Effect::Garbage => self.garbage += 1,
+            Effect::Nothing => {}
+        }
+    }
+
+    // What came between `self` and the later `to` of the same run, such
+    // that `self.then(self.until(to)) == to`.
+    fn until(self, to: Run) -> Run {
+        let opens = to.opens - self.opens;
+        Run {
+            exit: to.exit,
+            delta: to.delta - self.delta,
+            opens,
+            score: to.score - self.score - opens * self.delta,
+            garbage: to.garbage - self.garbage,
+        }
+    }
+
+    fn then(self, next: Run) -> Run {
+        Run {
+            exit: next.exit,
+            delta: self.delta + next.delta,
+            opens: self.opens + next.opens,
+            score: self.score + next.score + next.opens * self.delta,
+            garbage: self.garbage + next.garbage,
+        }
+    }
+}
+
+/// Everything about a chunk of a stream needed to combine it with its
+/// neighbours, without knowing how deep it starts or whether it starts
+/// inside garbage: one run of the chunk for each state it could begin in.
+///
+/// Combining is associative, so chunks can be summarized in any grouping
+/// and still give the answers the sequential `Scanner` does. Depth is
+/// tracked signed here, where the scanner stops at 0, so a stream with
+/// more `}` than `{` can come out differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    runs: [Run; 4],
+}
+
+impl Default for Summary {
+    /// The summary of no input at all.
+    fn default() -> Self {
+        Self {
+            runs: STATES.map(|exit| Run {
+                exit,
+                delta: 0,
+                opens: 0,
+                score: 0,
+                garbage: 0,
+            }),
+        }
+    }
+}
+
+impl Summary {
+    /// Scans `chunk` once, following every starting state side by side.
+    /// Two runs that reach the same state behave the same from then on, so
+    /// one of them stops there and takes the rest from the other at the
+    /// end. Runs meet at the first `<` or `>` outside a cancel, so most of
+    /// a chunk is scanned for one state only.
+    pub fn of(chunk: &[u8]) -> Self {
+        let mut runs = Self::default().runs;
+        let mut active: Vec<usize> = (0..runs.len()).collect();
+        // each run that stopped, the run it met, and both as they were then
+        let mut met = Vec::new();
+
+        let mut bytes = chunk.iter();
+        while active.len() > 1 {
+            let Some(&byte) = bytes.next() else {
+                break;
+            };
+            for &i in &active {
+                runs[i].feed(byte);
+            }
+
+            let mut j = 1;
+            while j < active.len() {
+                let (lane, exit) = (active[j], runs[active[j]].exit);
+                match active[..j].iter().find(|&&k| runs[k].exit == exit) {
+                    Some(&leader) => {
+                        met.push((lane, leader, runs[lane], runs[leader]));
+                        active.remove(j);
+                    }
+                    None => j += 1,
+                }
+            }
+        }
+
+        let last = &mut runs[active[0]];
+        for &byte in bytes {
+            last.feed(byte);
+        }
+
+        // a run that another one met may itself have stopped later on, so
+        // the latest meetings are settled first
+        for (lane, leader, lane_then, leader_then) in met.into_iter().rev() {
+            runs[lane] = lane_then.then(leader_then.until(runs[leader]));
+        }
+
+        Self { runs }
+    }
+
+    /// The summary of this chunk followed directly by `next`.
+    pub fn then(&self, next: &Summary) -> Summary {
+        Summary {
+            runs: self.runs.map(|run| run.then(next.runs[run.exit as usize])),
+        }
+    }
+
+    /// The answers, taking this to be a whole stream.
+    pub fn counts(&self) -> Counts {
+        let run = self.runs[State::Group as usize];
+        Counts {
+            score: run.score as u64,
+            garbage: run.garbage,
+        }
+    }
+}
+
+/// Splits `input` into a chunk per thread, summarizes them side by side,
+/// and combines the results.
+pub fn score_parallel(input: &[u8], threads: usize) -> Summary {
+    let size = input.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = input
+            .chunks(size)
+            .map(|chunk| scope.spawn(|| Summary::of(chunk)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .fold(Summary::default(), |acc, summary| acc.then(&summary))
+    })
+}
+
+/// Like `stream::scan`, but scores each large block it reads across
+/// `threads` threads.
+pub fn scan_parallel(mut reader: impl Read, threads: usize) -> io::Result<Counts> {
+    let mut summary = Summary::default();
+    let mut buf = vec![0; BLOCK_SIZE];
+
+    loop {
+        // fill the block as far as possible, so the threads get a fair
+        // share of work even from a reader that hands out little at a time
+        let mut len = 0;
+        while len < buf.len() {
+            match reader.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if len == 0 {
+            return Ok(summary.counts());
+        }
+        summary = summary.then(&score_parallel(&buf[..len], threads));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day9::stream::scan;
+    use crate::day9::Thing;
+    use crate::testutil::lcg;
+
+    // A well-formed stream with plenty of nesting, garbage and escapes.
+    fn generate(groups: usize) -> String {
+        let mut next = lcg(4242);
+
+        let mut out = String::from("{");
+        let mut depth = 1;
+        let mut opened = 1;
+        let mut first = true;
+        while depth > 0 {
+            match next(6) {
+                0..=2 if opened < groups => {
+                    out.push_str(if first { "{" } else { ",{" });
+                    depth += 1;
+                    opened += 1;
+                    first = true;
+                    continue;
+                }
+                3 => {
+                    out.push_str(if first { "<" } else { ",<" });
+                    for _ in 0..next(8) {
+                        out.push_str(["a", "!>", "!!", "{", "}", "<", ","][next(7)]);
+                    }
+                    out.push('>');
+                }
+                // keep the outermost group open until every group is in
+                _ if depth == 1 && opened < groups => continue,
+                _ => {
+                    out.push('}');
+                    depth -= 1;
+                }
+            }
+            first = false;
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_splits() {
+        // splits land inside multi-byte characters too, cancelled or not
+        for input in [
+            "{{<a!>b>},{{},<!!x,{yz>,{<!>>}},<>}",
+            "{<!é>,{<é!😀a>,<!€>}}",
+        ] {
+            let whole = Summary::of(input.as_bytes());
+            for i in 0..=input.len() {
+                let (head, tail) = input.as_bytes().split_at(i);
+                assert_eq!(Summary::of(head).then(&Summary::of(tail)), whole);
+            }
+            assert_eq!(whole.counts(), scan(input.as_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_associative() {
+        let input = generate(200);
+        let bytes = input.as_bytes();
+        let (a, rest) = bytes.split_at(bytes.len() / 3);
+        let (b, c) = rest.split_at(rest.len() / 2);
+        let (a, b, c) = (Summary::of(a), Summary::of(b), Summary::of(c));
+
+        assert_eq!(a.then(&b).then(&c), a.then(&b.then(&c)));
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let input = generate(5000);
+        let (_, thing) = Thing::parse(&input).unwrap();
+        assert_eq!(thing.groups().count(), 5000);
+        let expected = Counts {
+            score: thing.score(),
+            garbage: thing.garbage_count(),
+        };
+        assert_eq!(scan(input.as_bytes()).unwrap(), expected);
+
+        for threads in [1, 2, 3, 8, 64] {
+            assert_eq!(score_parallel(input.as_bytes(), threads).counts(), expected);
+        }
+        assert_eq!(scan_parallel(input.as_bytes(), 4).unwrap(), expected);
+    }
+}
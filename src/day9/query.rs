@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use super::Thing;
+
+/// A group met while walking a tree, with how deep it sits (the outermost
+/// group is at depth 1, as for scoring).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Visit<'a> {
+    pub group: &'a Thing,
+    pub depth: usize,
+}
+
+/// Every group in a tree, parents before their children, in input order.
+///
+/// The walk keeps one stack of where it is in each group on the way down,
+/// so each step costs the same however deep it goes. `path` builds the
+/// child indices to the latest group from that stack, only when asked.
+pub struct Groups<'a> {
+    root: Option<&'a Thing>,
+    // the children of each group from the root down to the latest one,
+    // with the index of the next child to look at in each
+    stack: Vec<(&'a [Thing], usize)>,
+}
+
+impl<'a> Groups<'a> {
+    /// The child indices leading from the root to the group last returned
+    /// by `next`, as taken by `Thing::get`.
+    pub fn path(&self) -> Vec<usize> {
+        let parents = self.stack.len().saturating_sub(1);
+        self.stack[..parents]
+            .iter()
+            .map(|&(_, next)| next - 1)
+            .collect()
+    }
+}
+
+impl<'a> Iterator for Groups<'a> {
+    type Item = Visit<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            self.stack.push((root.children(), 0));
+            return Some(Visit {
+                group: root,
+                depth: 1,
+            });
+        }
+
+        loop {
+            let (children, next) = self.stack.last_mut()?;
+            match children[*next..]
+                .iter()
+                .position(|child| matches!(child, Thing::Group { .. }))
+            {
+                Some(i) => {
+                    let group = &children[*next + i];
+                    *next += i + 1;
+                    self.stack.push((group.children(), 0));
+                    return Some(Visit {
+                        group,
+                        depth: self.stack.len(),
+                    });
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Thing {
+    /// Walks the groups without recursing.
+    pub fn groups(&self) -> Groups<'_> {
+        let root = match self {
+            Thing::Group { .. } => Some(self),
+            Thing::Garbage { .. } => None,
+        };
+
+        Groups {
+            root,
+            stack: Vec::new(),
+        }
+    }
+
+    // Calls `f` with every group and its depth, in no particular order.
+    fn each_group<'a>(&'a self, mut f: impl FnMut(&'a Thing, usize)) {
+        let mut stack = vec![(self, 1)];
+        while let Some((thing, depth)) = stack.pop() {
+            if let Thing::Group { children, .. } = thing {
+                f(thing, depth);
+                stack.extend(children.iter().map(|thing| (thing, depth + 1)));
+            }
+        }
+    }
+
+    /// Follows child indices down from here, as in `Groups::path`.
+    pub fn get(&self, path: &[usize]) -> Option<&Thing> {
+        path.iter()
+            .try_fold(self, |thing, &i| thing.children().get(i))
+    }
+
+    /// The depth of the most deeply nested group, or 0 if there are none.
+    pub fn max_depth(&self) -> usize {
+        let mut max = 0;
+        self.each_group(|_, depth| max = max.max(depth));
+        max
+    }
+
+    /// How many groups there are at each depth: the first element counts
+    /// depth 1, the next depth 2, and so on.
+    pub fn groups_per_depth(&self) -> Vec<usize> {
+        let mut counts = Vec::new();
+        self.each_group(|_, depth| {
+            if counts.len() < depth {
+                counts.resize(depth, 0);
+            }
+            counts[depth - 1] += 1;
+        });
+
+        counts
+    }
+
+    /// The garbage with the most bytes left after cancelling, counted as
+    /// `garbage_count` does, the first one on a tie.
+    pub fn largest_garbage(&self) -> Option<&Thing> {
+        let mut largest: Option<(&Thing, usize)> = None;
+        let mut stack = vec![self];
+        while let Some(thing) = stack.pop() {
+            match thing {
+                Thing::Garbage { text, .. } => {
+                    if largest.is_none_or(|(_, len)| text.len() > len) {
+                        largest = Some((thing, text.len()));
+                    }
+                }
+                Thing::Group { children, .. } => stack.extend(children.iter().rev()),
+            }
+        }
+
+        largest.map(|(thing, _)| thing)
+    }
+
+    /// For each number of children a group has, garbage included, how many
+    /// groups have that many.
+    pub fn child_count_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        self.each_group(|group, _| {
+            *histogram.entry(group.children().len()).or_insert(0) += 1;
+        });
+
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "{{<ab>},{{},<!!xyz>,{<a>}},<!>>,{<c>}}";
+
+    #[test]
+    fn test_groups() {
+        let (_, thing) = Thing::parse(EXAMPLE).unwrap();
+        let mut groups = thing.groups();
+        let mut visits = Vec::new();
+        while let Some(visit) = groups.next() {
+            assert_eq!(thing.get(&groups.path()), Some(visit.group));
+            visits.push((visit.depth, groups.path()));
+        }
+
+        assert_eq!(
+            visits,
+            vec![
+                (1, vec![]),
+                (2, vec![0]),
+                (2, vec![1]),
+                (3, vec![1, 0]),
+                (3, vec![1, 2]),
+                (2, vec![3]),
+            ]
+        );
+        assert_eq!(
+            thing.groups().map(|visit| visit.depth as u64).sum::<u64>(),
+            thing.score()
+        );
+        assert_eq!(&EXAMPLE[thing.get(&[1, 2]).unwrap().span()], "{<a>}");
+        assert_eq!(thing.get(&[0, 1]), None);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let (_, thing) = Thing::parse(EXAMPLE).unwrap();
+
+        assert_eq!(thing.max_depth(), 3);
+        assert_eq!(thing.groups_per_depth(), vec![1, 3, 2]);
+        assert_eq!(&EXAMPLE[thing.largest_garbage().unwrap().span()], "<!!xyz>");
+        assert_eq!(
+            thing.child_count_histogram(),
+            BTreeMap::from([(0, 1), (1, 3), (3, 1), (4, 1)])
+        );
+    }
+
+    #[test]
+    fn test_deep() {
+        let depth = 500_000;
+        let input = format!("{}<ab>{}", "{".repeat(depth), "}".repeat(depth));
+        let (_, thing) = Thing::parse(&input).unwrap();
+
+        let mut groups = thing.groups();
+        assert_eq!(groups.by_ref().map(|visit| visit.depth).max(), Some(depth));
+        assert_eq!(groups.path(), vec![]);
+        assert_eq!(thing.max_depth(), depth);
+        assert_eq!(thing.groups_per_depth(), vec![1; depth]);
+        assert_eq!(thing.child_count_histogram(), BTreeMap::from([(1, depth)]));
+
+        let mut groups = thing.groups();
+        let deepest = groups.nth(depth - 1).unwrap();
+        assert_eq!(deepest.depth, depth);
+        assert_eq!(groups.path(), vec![0; depth - 1]);
+    }
+
+    #[test]
+    fn test_garbage_only() {
+        let (_, thing) = Thing::parse("<abc>").unwrap();
+
+        assert_eq!(thing.groups().count(), 0);
+        assert_eq!(thing.max_depth(), 0);
+        assert_eq!(thing.groups_per_depth(), Vec::<usize>::new());
+        assert_eq!(thing.largest_garbage(), Some(&thing));
+    }
+}
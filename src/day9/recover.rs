@@ -0,0 +1,213 @@
+use std::fmt;
+
+use nom::Offset;
+
+use super::Thing;
+
+/// Something wrong with a stream, found while parsing it leniently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The input ran out with the group still open.
+    UnclosedGroup,
+    /// The input ran out with the garbage still open.
+    UnclosedGarbage,
+    /// A `}` with no group open to close.
+    StrayClose,
+    /// A character that can't appear outside garbage.
+    Unexpected(char),
+}
+
+/// A problem, and the byte it was found at. For unclosed groups and
+/// garbage, that's where they were opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pos: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            Problem::UnclosedGroup => write!(f, "unclosed group opened at byte {}", self.pos),
+            Problem::UnclosedGarbage => {
+                write!(f, "unclosed garbage opened at byte {}", self.pos)
+            }
+            Problem::StrayClose => write!(f, "stray `}}` at byte {}", self.pos),
+            Problem::Unexpected(c) => write!(f, "unexpected `{}` at byte {}", c, self.pos),
+        }
+    }
+}
+
+/// What `Thing::parse_lenient` made of a stream: everything at the top
+/// level, which is normally a single group, and what was wrong with it.
+#[derive(Debug, PartialEq)]
+pub struct Recovered {
+    pub things: Vec<Thing>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Recovered {
+    pub fn score(&self) -> u64 {
+        self.things.iter().map(Thing::score).sum()
+    }
+
+    pub fn garbage_count(&self) -> u64 {
+        self.things.iter().map(Thing::garbage_count).sum()
+    }
+}
+
+impl Thing {
+    /// Parses as much of a damaged stream as it can. A stray `}` or an
+    /// unexpected character is skipped; groups and garbage still open at
+    /// the end are closed there, so a truncated stream keeps everything
+    /// seen so far. Whitespace and extra commas are let through quietly.
+    pub fn parse_lenient(input: &str) -> Recovered {
+        let mut things = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut open: Vec<(usize, Vec<Thing>)> = Vec::new();
+        let mut rest = input;
+
+        while let Some(c) = rest.chars().next() {
+            let start = input.offset(rest);
+            let mut after = &rest[c.len_utf8()..];
+            let mut problem = |problem| {
+                diagnostics.push(Diagnostic {
+                    pos: start,
+                    problem,
+                })
+            };
+
+            let thing = match c {
+                '{' => {
+                    open.push((start, Vec::new()));
+                    None
+                }
+                '}' => match open.pop() {
+                    Some((start, children)) => Some(Thing::Group {
+                        span: start..input.offset(after),
+                        children,
+                    }),
+                    None => {
+                        problem(Problem::StrayClose);
+                        None
+                    }
+                },
+                '<' => {
+                    let (end, (text, cancelled)) =
+                        Thing::garbage_body(input, after).expect("garbage bodies always parse");
+                    after = match end.strip_prefix('>') {
+                        Some(end) => end,
+                        None => {
+                            problem(Problem::UnclosedGarbage);
+                            &end[end.len()..]
+                        }
+                    };
+
+                    Some(Thing::Garbage {
+                        span: start..input.offset(after),
+                        text,
+                        cancelled,
+                    })
+                }
+                ',' => None,
+                c if c.is_whitespace() => None,
+                c => {
+                    problem(Problem::Unexpected(c));
+                    None
+                }
+            };
+
+            if let Some(thing) = thing {
+                match open.last_mut() {
+                    Some((_, children)) => children.push(thing),
+                    None => things.push(thing),
+                }
+            }
+            rest = after;
+        }
+
+        while let Some((start, children)) = open.pop() {
+            diagnostics.push(Diagnostic {
+                pos: start,
+                problem: Problem::UnclosedGroup,
+            });
+
+            let thing = Thing::Group {
+                span: start..input.len(),
+                children,
+            };
+            match open.last_mut() {
+                Some((_, children)) => children.push(thing),
+                None => things.push(thing),
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.pos);
+        Recovered {
+            things,
+            diagnostics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(recovered: &Recovered) -> Vec<String> {
+        recovered
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let input = "{{<ab>},{<!!>},{<a!>},{<ab>}}\n";
+        let recovered = Thing::parse_lenient(input);
+        let (_, thing) = Thing::parse(input).unwrap();
+
+        assert_eq!(recovered.things, vec![thing]);
+        assert_eq!(recovered.diagnostics, vec![]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let recovered = Thing::parse_lenient("{{<a>},{<b>},{{}");
+        assert_eq!(
+            messages(&recovered),
+            vec![
+                "unclosed group opened at byte 0",
+                "unclosed group opened at byte 13",
+            ]
+        );
+        assert_eq!(recovered.score(), 1 + 2 + 2 + 2 + 3);
+        assert_eq!(recovered.garbage_count(), 2);
+        assert_eq!(recovered.things[0].span(), 0..16);
+
+        let recovered = Thing::parse_lenient("{{<a>},{<bc!>");
+        assert_eq!(
+            messages(&recovered),
+            vec![
+                "unclosed group opened at byte 0",
+                "unclosed group opened at byte 7",
+                "unclosed garbage opened at byte 8",
+            ]
+        );
+        assert_eq!(recovered.score(), 1 + 2 + 2);
+        assert_eq!(recovered.garbage_count(), 3);
+    }
+
+    #[test]
+    fn test_stray() {
+        let recovered = Thing::parse_lenient("{{}}},x{<a>}");
+        assert_eq!(
+            messages(&recovered),
+            vec!["stray `}` at byte 4", "unexpected `x` at byte 6"]
+        );
+        assert_eq!(recovered.things.len(), 2);
+        assert_eq!(recovered.score(), 1 + 2 + 1);
+        assert_eq!(recovered.garbage_count(), 1);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use super::Thing;
+
+/// How `Thing::serialize` lays out a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Everything on one line, children separated by single commas, and
+    /// garbage written as just its text. Text never holds a `!` or `>`, so
+    /// nothing needs escaping.
+    Canonical,
+    /// Like `Canonical`, with all the garbage left out, so a tree that is
+    /// nothing but garbage comes out empty. Scores stay the same.
+    Minified,
+    /// One thing per line, nested groups indented by two spaces. The
+    /// whitespace means it takes `Thing::parse_lenient` to read it back.
+    Pretty,
+}
+
+// What's left to write, newest last.
+enum Item<'a> {
+    Thing(&'a Thing, usize),
+    Comma,
+    Close(usize),
+}
+
+impl Thing {
+    /// Writes the tree back out as a stream. Cancelled characters are gone
+    /// from the tree, so they're gone from the output too.
+    ///
+    /// `Canonical` and `Minified` output can be read back with
+    /// `Thing::parse`, as long as there is something left of it. `Pretty`
+    /// output can't, and neither can the puzzle solvers: its whitespace
+    /// sits outside any garbage, which only `Thing::parse_lenient` allows.
+    pub fn serialize(&self, style: Style) -> String {
+        let mut out = String::new();
+        let mut stack = vec![Item::Thing(self, 0)];
+        let pretty = style == Style::Pretty;
+        let indent = |out: &mut String, depth: usize| {
+            if pretty {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', 2 * depth));
+            }
+        };
+
+        while let Some(item) = stack.pop() {
+            match item {
+                // a group's garbage children are filtered out below, so
+                // this is only ever the root
+                Item::Thing(Thing::Garbage { .. }, _) if style == Style::Minified => {}
+                Item::Thing(Thing::Garbage { text, .. }, _) => {
+                    out.push('<');
+                    out.push_str(text);
+                    out.push('>');
+                }
+                Item::Thing(Thing::Group { children, .. }, depth) => {
+                    let children: Vec<_> = children
+                        .iter()
+                        .filter(|child| {
+                            style != Style::Minified || matches!(child, Thing::Group { .. })
+                        })
+                        .collect();
+
+                    out.push('{');
+                    if children.is_empty() {
+                        out.push('}');
+                        continue;
+                    }
+
+                    stack.push(Item::Close(depth));
+                    for (i, child) in children.into_iter().enumerate().rev() {
+                        stack.push(Item::Thing(child, depth + 1));
+                        if i > 0 {
+                            stack.push(Item::Comma);
+                        }
+                    }
+                    indent(&mut out, depth + 1);
+                }
+                Item::Comma => {
+                    out.push(',');
+                    // the next item is always the thing the comma comes before
+                    if let Some(Item::Thing(_, depth)) = stack.last() {
+                        indent(&mut out, *depth);
+                    }
+                }
+                Item::Close(depth) => {
+                    indent(&mut out, depth);
+                    out.push('}');
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Thing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.serialize(Style::Canonical))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "{{<a!>b>},{{},<!!x,{yz>,{<!>>}},<>}";
+
+    #[test]
+    fn test_canonical() {
+        let (_, thing) = Thing::parse(EXAMPLE).unwrap();
+        let text = thing.to_string();
+        assert_eq!(text, "{{<ab>},{{},<x,{yz>,{<>}},<>}");
+
+        let (rest, again) = Thing::parse(&text).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(again.score(), thing.score());
+        assert_eq!(again.garbage_count(), thing.garbage_count());
+        assert_eq!(again.to_string(), text);
+    }
+
+    #[test]
+    fn test_minified() {
+        let (_, thing) = Thing::parse(EXAMPLE).unwrap();
+        let text = thing.serialize(Style::Minified);
+        assert_eq!(text, "{{},{{},{}}}");
+
+        let (_, again) = Thing::parse(&text).unwrap();
+        assert_eq!(again.score(), thing.score());
+        assert_eq!(again.garbage_count(), 0);
+
+        let (_, garbage) = Thing::parse("<abc>").unwrap();
+        assert_eq!(garbage.serialize(Style::Minified), "");
+        assert_eq!(garbage.serialize(Style::Canonical), "<abc>");
+    }
+
+    #[test]
+    fn test_pretty() {
+        let (_, thing) = Thing::parse(EXAMPLE).unwrap();
+        let text = thing.serialize(Style::Pretty);
+        assert_eq!(
+            text,
+            "{\n  \
+               {\n    \
+                 <ab>\n  \
+               },\n  \
+               {\n    \
+                 {},\n    \
+                 <x,{yz>,\n    \
+                 {\n      \
+                   <>\n    \
+                 }\n  \
+               },\n  \
+               <>\n\
+             }"
+        );
+
+        assert!(Thing::parse(&text).is_err());
+        let again = Thing::parse_lenient(&text);
+        assert_eq!(again.diagnostics, vec![]);
+        assert_eq!(again.score(), thing.score());
+        assert_eq!(again.garbage_count(), thing.garbage_count());
+    }
+
+    #[test]
+    fn test_deep() {
+        let depth = 200_000;
+        let input = format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+        let (_, thing) = Thing::parse(&input).unwrap();
+        assert_eq!(thing.serialize(Style::Canonical), input);
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Read};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The two answers for a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub score: u64,
+    pub garbage: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum State {
+    #[default]
+    Group,
+    Garbage,
+    // just after a `!`, inside garbage
+    Cancel,
+    // partway through a character that a `!` cancelled, which takes up
+    // more than one byte
+    Cancelled,
+}
+
+// What a byte means, besides moving between states.
+pub(super) enum Effect {
+    Open,
+    Close,
+    Garbage,
+    Nothing,
+}
+
+impl State {
+    pub(super) fn next(self, byte: u8) -> (State, Effect) {
+        match (self, byte) {
+            (State::Group, b'{') => (State::Group, Effect::Open),
+            (State::Group, b'}') => (State::Group, Effect::Close),
+            (State::Group, b'<') => (State::Garbage, Effect::Nothing),
+            (State::Group, _) => (State::Group, Effect::Nothing),
+            (State::Garbage, b'!') => (State::Cancel, Effect::Nothing),
+            (State::Garbage, b'>') => (State::Group, Effect::Nothing),
+            (State::Garbage, _) => (State::Garbage, Effect::Garbage),
+            // the tree cancels a whole character, so the continuation
+            // bytes of one are skipped too
+            (State::Cancel, 0xc0..) => (State::Cancelled, Effect::Nothing),
+            (State::Cancel, _) => (State::Garbage, Effect::Nothing),
+            (State::Cancelled, 0x80..=0xbf) => (State::Cancelled, Effect::Nothing),
+            (State::Cancelled, _) => State::Garbage.next(byte),
+        }
+    }
+}
+
+/// Scores a stream a byte at a time, without building a tree, so memory
+/// use doesn't depend on the size of the input. Bytes can be fed in
+/// pieces of any size; the state carries across.
+#[derive(Debug, Clone, Default)]
+pub struct Scanner {
+    state: State,
+    depth: u64,
+    counts: Counts,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let (state, effect) = self.state.next(byte);
+            match effect {
+                Effect::Open => {
+                    self.depth += 1;
+                    self.counts.score += self.depth;
+                }
+                Effect::Close => self.depth = self.depth.saturating_sub(1),
+                Effect::Garbage => self.counts.garbage += 1,
+                Effect::Nothing => {}
+            }
+            self.state = state;
+        }
+    }
+
+    pub fn counts(&self) -> Counts {
+        self.counts
+    }
+}
+
+/// Reads `reader` to the end in fixed size chunks and scores it.
+pub fn scan(mut reader: impl Read) -> io::Result<Counts> {
+    let mut scanner = Scanner::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(scanner.counts()),
+            Ok(n) => scanner.feed(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day9::{solve_part1, solve_part2};
+
+    // Hands out at most one byte per read, so every state gets split
+    // across a chunk boundary somewhere.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(slot)) => {
+                    *slot = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_tree() {
+        for input in [
+            "{}",
+            "{{{}}}",
+            "{{},{}}",
+            "{{{},{},{{}}}}",
+            "{<a>,<a>,<a>,<a>}",
+            "{{<ab>},{<ab>},{<ab>},{<ab>}}",
+            "{{<!!>},{<!!>},{<!!>},{<!!>}}",
+            "{{<a!>},{<a!>},{<a!>},{<ab>}}",
+            "{<{o\"i!a,<{i<a>}",
+            "{<!!!>>,{<random characters>}}",
+            "{<!é>}",
+            "{<é!€a!😀>,{<ü>}}",
+            "{<!é!>>}",
+        ] {
+            let expected = Counts {
+                score: solve_part1(input),
+                garbage: solve_part2(input),
+            };
+            assert_eq!(scan(input.as_bytes()).unwrap(), expected, "{}", input);
+            assert_eq!(
+                scan(Trickle(input.as_bytes())).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_large() {
+        // a million nested groups around a little garbage, generated
+        // on the fly rather than held in memory
+        let depth = 1_000_000u64;
+        let opening = io::repeat(b'{').take(depth);
+        let garbage = b"<a!>b>".repeat(3);
+        let closing = io::repeat(b'}').take(depth);
+
+        let counts = scan(opening.chain(&garbage[..]).chain(closing)).unwrap();
+        assert_eq!(counts.score, depth * (depth + 1) / 2);
+        assert_eq!(counts.garbage, 3 * 2);
+    }
+}
@@ -14,4 +14,7 @@ pub mod day7;
 pub mod day8;
 pub mod day9;
 
+#[cfg(test)]
+mod testutil;
+
 aoc_lib! { year = 2017 }
@@ -0,0 +1,8 @@
+/// A small linear congruential generator, so generated test inputs are the
+/// same on every run. Each call gives a number below `n`.
+pub fn lcg(mut seed: u64) -> impl FnMut(usize) -> usize {
+    move |n| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (seed >> 33) as usize % n
+    }
+}